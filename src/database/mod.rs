@@ -0,0 +1,397 @@
+use crate::pkmn_data::Series;
+use crate::ptcgio_data::{Card, Set};
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, Transaction};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The datasets a database holds, each imported on its own and replacing its previous import
+#[derive(
+    Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, clap::ValueEnum, strum::Display,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Dataset {
+    Ptcgio,
+    Pkmn,
+    /// The reconciled dataset the diff writes to `merged`
+    Merged,
+}
+
+/// The sets of one dataset by name, in the dataset's order
+pub type Sets = Vec<(String, Vec<Card>)>;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS scrapes (
+    id INTEGER PRIMARY KEY,
+    dataset TEXT NOT NULL,
+    origin TEXT NOT NULL,
+    imported_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+    sets INTEGER NOT NULL,
+    cards INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS series (
+    id INTEGER PRIMARY KEY,
+    dataset TEXT NOT NULL,
+    name TEXT NOT NULL,
+    UNIQUE (dataset, name)
+);
+CREATE TABLE IF NOT EXISTS sets (
+    id INTEGER PRIMARY KEY,
+    series_id INTEGER NOT NULL REFERENCES series (id) ON DELETE CASCADE,
+    dataset TEXT NOT NULL,
+    set_id TEXT,
+    name TEXT NOT NULL,
+    code TEXT,
+    release_date TEXT
+);
+CREATE TABLE IF NOT EXISTS cards (
+    id INTEGER PRIMARY KEY,
+    set_id INTEGER NOT NULL REFERENCES sets (id) ON DELETE CASCADE,
+    dataset TEXT NOT NULL,
+    card_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    supertype TEXT NOT NULL,
+    subtypes TEXT,
+    types TEXT,
+    hp TEXT,
+    evolves_from TEXT,
+    number TEXT NOT NULL,
+    artist TEXT,
+    rarity TEXT,
+    flavor_text TEXT,
+    regulation_mark TEXT,
+    card TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS cards_by_name ON cards (name);
+CREATE INDEX IF NOT EXISTS cards_by_card_id ON cards (card_id);
+CREATE TABLE IF NOT EXISTS attacks (
+    card_id INTEGER NOT NULL REFERENCES cards (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    name TEXT,
+    cost TEXT,
+    converted_energy_cost INTEGER,
+    damage TEXT,
+    text TEXT,
+    PRIMARY KEY (card_id, position)
+);
+CREATE TABLE IF NOT EXISTS abilities (
+    card_id INTEGER NOT NULL REFERENCES cards (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    name TEXT,
+    type TEXT,
+    text TEXT,
+    PRIMARY KEY (card_id, position)
+);
+CREATE TABLE IF NOT EXISTS legalities (
+    card_id INTEGER NOT NULL REFERENCES cards (id) ON DELETE CASCADE,
+    format TEXT NOT NULL,
+    legality TEXT NOT NULL,
+    PRIMARY KEY (card_id, format)
+);
+";
+
+/// The sets and cards of the datasets in SQLite, for querying them with SQL. Cards keep their
+/// lists like subtypes as JSON arrays and the whole card as JSON in `cards.card`.
+pub struct Database {
+    connection: Connection,
+}
+
+impl Database {
+    /// Opens the database at `path`, creating it and its tables as needed
+    pub fn open(path: impl AsRef<Path>) -> Result<Database> {
+        let path = path.as_ref();
+        let connection = Connection::open(path)
+            .with_context(|| format!("Failed to open the database {}", path.display()))?;
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Database { connection })
+    }
+
+    /// Replaces the ptcgio or merged dataset with `sets`, which carry the name of their series
+    pub fn import_sets(&mut self, dataset: Dataset, origin: &str, sets: &[Set]) -> Result<usize> {
+        let tx = self.connection.transaction()?;
+        let import = Import::start(&tx, dataset)?;
+        let mut cards = 0;
+        for set in sets {
+            let series_id = import.series(&set.series)?;
+            let set_id = import.set(
+                series_id,
+                Some(&set.id),
+                &set.name,
+                set.ptcgo_code.as_deref(),
+                Some(&set.release_date),
+            )?;
+            for card in &set.cards {
+                import.card(set_id, card)?;
+            }
+            cards += set.cards.len();
+        }
+        import.finish(origin, sets.len(), cards)?;
+        tx.commit()?;
+        Ok(cards)
+    }
+
+    /// Replaces the pkmncards dataset with `all_series`
+    pub fn import_series(&mut self, origin: &str, all_series: &[Series]) -> Result<usize> {
+        let tx = self.connection.transaction()?;
+        let import = Import::start(&tx, Dataset::Pkmn)?;
+        let mut cards = 0;
+        for series in all_series {
+            let series_id = import.series(&series.name)?;
+            for set in &series.sets {
+                let set_id = import.set(series_id, None, &set.name, None, None)?;
+                for card in &set.cards {
+                    import.card(set_id, card)?;
+                }
+                cards += set.cards.len();
+            }
+        }
+        let sets = all_series.iter().map(|series| series.sets.len()).sum();
+        import.finish(origin, sets, cards)?;
+        tx.commit()?;
+        Ok(cards)
+    }
+}
+
+/// The import of one dataset, inside the transaction that first cleared it
+struct Import<'a> {
+    tx: &'a Transaction<'a>,
+    dataset: String,
+}
+
+impl<'a> Import<'a> {
+    fn start(tx: &'a Transaction<'a>, dataset: Dataset) -> Result<Self> {
+        let dataset = dataset.to_string();
+        tx.execute("DELETE FROM series WHERE dataset = ?1", [&dataset])?;
+        Ok(Import { tx, dataset })
+    }
+
+    fn series(&self, name: &str) -> Result<i64> {
+        Ok(self.tx.query_row(
+            "INSERT INTO series (dataset, name) VALUES (?1, ?2)
+             ON CONFLICT (dataset, name) DO UPDATE SET name = excluded.name
+             RETURNING id",
+            params![self.dataset, name],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn set(
+        &self,
+        series_id: i64,
+        set_id: Option<&str>,
+        name: &str,
+        code: Option<&str>,
+        release_date: Option<&str>,
+    ) -> Result<i64> {
+        self.tx.execute(
+            "INSERT INTO sets (series_id, dataset, set_id, name, code, release_date)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![series_id, self.dataset, set_id, name, code, release_date],
+        )?;
+        Ok(self.tx.last_insert_rowid())
+    }
+
+    fn card(&self, set_id: i64, card: &Card) -> Result<()> {
+        let json_list = |list: &Option<Vec<String>>| -> Result<Option<String>> {
+            list.as_ref()
+                .map(|list| Ok(serde_json::to_string(list)?))
+                .transpose()
+        };
+        self.tx.execute(
+            "INSERT INTO cards (set_id, dataset, card_id, name, supertype, subtypes, types, hp,
+                 evolves_from, number, artist, rarity, flavor_text, regulation_mark, card)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            params![
+                set_id,
+                self.dataset,
+                card.id,
+                card.name,
+                card.supertype,
+                json_list(&card.subtypes)?,
+                json_list(&card.types)?,
+                card.hp,
+                card.evolves_from,
+                card.number,
+                card.artist,
+                card.rarity,
+                card.flavor_text,
+                card.regulation_mark,
+                serde_json::to_string(card)?,
+            ],
+        )?;
+        let card_id = self.tx.last_insert_rowid();
+
+        for (position, attack) in card.attacks.iter().flatten().enumerate() {
+            let text = |key: &str| attack.get(key).and_then(|value| value.as_str());
+            let cost = attack.get("cost").map(serde_json::to_string).transpose()?;
+            self.tx.execute(
+                "INSERT INTO attacks (card_id, position, name, cost, converted_energy_cost,
+                     damage, text)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    card_id,
+                    position,
+                    text("name"),
+                    cost,
+                    attack
+                        .get("convertedEnergyCost")
+                        .and_then(|value| value.as_i64()),
+                    text("damage"),
+                    text("text"),
+                ],
+            )?;
+        }
+        for (position, ability) in card.abilities.iter().flatten().enumerate() {
+            self.tx.execute(
+                "INSERT INTO abilities (card_id, position, name, type, text)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    card_id,
+                    position,
+                    ability.get("name"),
+                    ability.get("type"),
+                    ability.get("text"),
+                ],
+            )?;
+        }
+        for (format, legality) in &card.legalities {
+            self.tx.execute(
+                "INSERT INTO legalities (card_id, format, legality) VALUES (?1, ?2, ?3)",
+                params![card_id, format, legality],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Records where the dataset came from and how much of it there was
+    fn finish(&self, origin: &str, sets: usize, cards: usize) -> Result<()> {
+        self.tx.execute(
+            "INSERT INTO scrapes (dataset, origin, sets, cards) VALUES (?1, ?2, ?3, ?4)",
+            params![self.dataset, origin, sets, cards],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkmn_data;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn card(id: &str, name: &str) -> Card {
+        Card {
+            subtypes: Some(vec!["Basic".to_string()]),
+            attacks: Some(vec![BTreeMap::from([
+                ("name".to_string(), json!("Thunder Shock")),
+                ("cost".to_string(), json!(["Lightning"])),
+                ("convertedEnergyCost".to_string(), json!(1)),
+                ("damage".to_string(), json!("10")),
+                ("text".to_string(), json!("Flip a coin.")),
+            ])]),
+            legalities: BTreeMap::from([("unlimited".to_string(), "Legal".to_string())]),
+            ..Card::stub(id, name)
+        }
+    }
+
+    fn ptcgio_set(id: &str, name: &str, cards: Vec<Card>) -> Set {
+        Set {
+            cards,
+            id: id.to_string(),
+            name: name.to_string(),
+            series: "Base".to_string(),
+            printed_total: 102,
+            total: 102,
+            legalities: BTreeMap::new(),
+            ptcgo_code: Some("BS".to_string()),
+            release_date: "1999/01/09".to_string(),
+            updated_at: "2020/08/14 09:35:00".to_string(),
+            images: BTreeMap::new(),
+        }
+    }
+
+    fn count(database: &Database, sql: &str) -> usize {
+        database
+            .connection
+            .query_row(sql, [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn import_both_datasets() {
+        let mut database = Database::open(":memory:").unwrap();
+        let sets = [
+            ptcgio_set("base1", "Base", vec![card("base1-58", "Pikachu")]),
+            ptcgio_set("base2", "Jungle", vec![card("base2-60", "Pikachu")]),
+        ];
+        assert_eq!(
+            database
+                .import_sets(Dataset::Ptcgio, "checkout", &sets)
+                .unwrap(),
+            2
+        );
+        let series = [pkmn_data::Series {
+            name: "Original Series".to_string(),
+            sets: vec![pkmn_data::Set::new(
+                "Base",
+                vec![card("base1-58", "Pikachu"), card("base1-14", "Raichu")],
+            )],
+        }];
+        assert_eq!(database.import_series("site", &series).unwrap(), 2);
+
+        assert_eq!(count(&database, "SELECT count(*) FROM series"), 2);
+        assert_eq!(
+            count(
+                &database,
+                "SELECT count(*) FROM cards JOIN sets ON cards.set_id = sets.id
+                 WHERE sets.dataset = 'ptcgio' AND sets.code = 'BS'"
+            ),
+            2
+        );
+        assert_eq!(
+            count(
+                &database,
+                "SELECT count(*) FROM attacks JOIN cards ON attacks.card_id = cards.id
+                 WHERE cards.dataset = 'pkmn' AND attacks.converted_energy_cost = 1"
+            ),
+            2
+        );
+        assert_eq!(
+            count(
+                &database,
+                "SELECT count(*) FROM cards, json_each(cards.subtypes)
+                 WHERE json_each.value = 'Basic'"
+            ),
+            4
+        );
+        assert_eq!(count(&database, "SELECT count(*) FROM legalities"), 4);
+        assert_eq!(count(&database, "SELECT sum(cards) FROM scrapes"), 4);
+    }
+
+    #[test]
+    fn reimport_replaces_the_dataset() {
+        let mut database = Database::open(":memory:").unwrap();
+        let sets = [ptcgio_set(
+            "base1",
+            "Base",
+            vec![card("base1-58", "Pikachu")],
+        )];
+        database
+            .import_sets(Dataset::Ptcgio, "checkout", &sets)
+            .unwrap();
+        database
+            .import_sets(Dataset::Merged, "merged", &sets)
+            .unwrap();
+        database
+            .import_sets(Dataset::Ptcgio, "checkout", &sets)
+            .unwrap();
+
+        assert_eq!(count(&database, "SELECT count(*) FROM sets"), 2);
+        assert_eq!(count(&database, "SELECT count(*) FROM cards"), 2);
+        assert_eq!(count(&database, "SELECT count(*) FROM attacks"), 2);
+        assert_eq!(count(&database, "SELECT count(*) FROM scrapes"), 3);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::diff::{parse_path, set_at_path, value_at_path};
+use crate::ptcgio_data::Card;
+use crate::reconcile::Source;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, BufReader};
+
+/// How a reviewer resolved a difference between ptcgio (left) and pkmncards (right)
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Decision {
+    AcceptLeft,
+    AcceptRight,
+    /// The difference is known and intentional, neither side is changed
+    Ignore,
+    /// Both sides are wrong, this is the correct value
+    Override(Value),
+}
+
+/// Which cards get rewritten when resolving decisions
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum Resolve {
+    /// Both datasets take the decided value so the difference no longer shows up in diffs
+    Both,
+    /// Only the pkmncards card takes the decided value, leaving the ptcgio card as the one
+    /// that needs correcting
+    Pkmn,
+}
+
+/// Reviewer decisions keyed by set, card number and field path
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(transparent)]
+pub struct Decisions {
+    sets: BTreeMap<String, BTreeMap<String, BTreeMap<String, Decision>>>,
+}
+
+impl Decisions {
+    pub async fn load(path: impl AsRef<Path>) -> Result<Decisions> {
+        let file = File::open(path).await?;
+        let mut reader = BufReader::new(file);
+        let mut buffer: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut buffer).await?;
+        Ok(serde_json::from_slice(&buffer)?)
+    }
+
+    pub fn get(&self, set: &str, number: &str, path: &str) -> Option<&Decision> {
+        self.sets.get(set)?.get(number)?.get(path)
+    }
+
+    /// All decisions made for a single card, keyed by field path
+    pub fn for_card(&self, set: &str, number: &str) -> impl Iterator<Item = (&str, &Decision)> {
+        self.sets
+            .get(set)
+            .and_then(|cards| cards.get(number))
+            .into_iter()
+            .flatten()
+            .map(|(path, decision)| (path.as_str(), decision))
+    }
+
+    /// The value a decision settles on, given what each dataset has at the decided path
+    pub fn outcome(
+        decision: &Decision,
+        ptcgio: Option<&Value>,
+        pkmn: Option<&Value>,
+    ) -> Option<(Source, Value)> {
+        match decision {
+            Decision::AcceptLeft => Some((Source::Ptcgio, ptcgio.cloned().unwrap_or(Value::Null))),
+            Decision::AcceptRight => Some((Source::Pkmn, pkmn.cloned().unwrap_or(Value::Null))),
+            Decision::Override(value) => Some((Source::Override, value.clone())),
+            Decision::Ignore => None,
+        }
+    }
+
+    /// Rewrites the cards of a set so that every decided difference disappears
+    pub fn resolve(
+        &self,
+        set: &str,
+        ptcgio: &mut [Card],
+        pkmn: &mut [Card],
+        resolve: Resolve,
+    ) -> Result<()> {
+        let Some(cards) = self.sets.get(set) else {
+            return Ok(());
+        };
+
+        for (number, decisions) in cards {
+            let (Some(ptcgio_card), Some(pkmn_card)) = (
+                numbered(ptcgio, set, number, "ptcgio")?,
+                numbered(pkmn, set, number, "pkmncards")?,
+            ) else {
+                log::warn!(
+                    "Decisions for {} #{} do not match a card on both sides",
+                    set,
+                    number
+                );
+                continue;
+            };
+
+            let mut ptcgio_value = serde_json::to_value(&*ptcgio_card)?;
+            let mut pkmn_value = serde_json::to_value(&*pkmn_card)?;
+            for (path, decision) in decisions {
+                let steps = parse_path(path)?;
+                let value = match Self::outcome(
+                    decision,
+                    value_at_path(&ptcgio_value, &steps),
+                    value_at_path(&pkmn_value, &steps),
+                ) {
+                    Some((_, value)) => value,
+                    // Silencing an ignored difference means pretending pkmncards agrees
+                    None => value_at_path(&ptcgio_value, &steps)
+                        .cloned()
+                        .unwrap_or(Value::Null),
+                };
+
+                if resolve == Resolve::Both && decision != &Decision::Ignore {
+                    set_at_path(&mut ptcgio_value, &steps, value.clone()).with_context(|| {
+                        format!("Failed to resolve {} of {} #{}", path, set, number)
+                    })?;
+                }
+                set_at_path(&mut pkmn_value, &steps, value).with_context(|| {
+                    format!("Failed to resolve {} of {} #{}", path, set, number)
+                })?;
+            }
+            *ptcgio_card = serde_json::from_value(ptcgio_value)?;
+            *pkmn_card = serde_json::from_value(pkmn_value)?;
+        }
+        Ok(())
+    }
+}
+
+/// The card of a set with the number, as decisions can't tell apart cards that share one
+fn numbered<'a>(
+    cards: &'a mut [Card],
+    set: &str,
+    number: &str,
+    dataset: &str,
+) -> Result<Option<&'a mut Card>> {
+    let mut matches = cards.iter_mut().filter(|card| card.number == number);
+    let card = matches.next();
+    if matches.next().is_some() {
+        bail!(
+            "Decisions for {} #{} are ambiguous as {} has more than one card with that number",
+            set,
+            number,
+            dataset
+        );
+    }
+    Ok(card)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn decisions(json: Value) -> Decisions {
+        serde_json::from_value(json!({ "Base": json })).unwrap()
+    }
+
+    /// Alakazam as ptcgio and pkmncards have it, differing in hp, flavor text, artist and rarity
+    fn alakazam() -> (Vec<Card>, Vec<Card>) {
+        let ptcgio = Card {
+            hp: Some("80".to_string()),
+            flavor_text: Some("Its brain can outperform a supercomputer.".to_string()),
+            artist: Some("Ken Sugimori".to_string()),
+            rarity: Some("Rare Holo".to_string()),
+            ..Card::stub("base1-1", "Alakazam")
+        };
+        let pkmn = Card {
+            hp: Some("90".to_string()),
+            flavor_text: None,
+            artist: Some("Sugimori".to_string()),
+            rarity: Some("Holo Rare".to_string()),
+            ..ptcgio.clone()
+        };
+        (vec![ptcgio, Card::stub("base1-2", "Blastoise")], vec![pkmn])
+    }
+
+    fn resolved(decisions: &Decisions, resolve: Resolve) -> (Card, Card) {
+        let (mut ptcgio, mut pkmn) = alakazam();
+        decisions
+            .resolve("Base", &mut ptcgio, &mut pkmn, resolve)
+            .unwrap();
+        (ptcgio.remove(0), pkmn.remove(0))
+    }
+
+    #[test]
+    fn resolve_each_kind_of_decision() {
+        let decisions = decisions(json!({"1": {
+            "hp": "accept-left",
+            "flavorText": "accept-right",
+            "artist": "ignore",
+            "rarity": {"override": "Rare"},
+        }}));
+        let (original, _) = alakazam();
+
+        let (ptcgio, pkmn) = resolved(&decisions, Resolve::Both);
+        assert_eq!(ptcgio, pkmn, "Every decided difference disappears");
+        assert_eq!(ptcgio.hp.as_deref(), Some("80"));
+        assert_eq!(ptcgio.flavor_text, None);
+        assert_eq!(ptcgio.artist.as_deref(), Some("Ken Sugimori"));
+        assert_eq!(ptcgio.rarity.as_deref(), Some("Rare"));
+
+        let (ptcgio, pkmn) = resolved(&decisions, Resolve::Pkmn);
+        assert_eq!(
+            ptcgio, original[0],
+            "Only pkmncards takes the decided values"
+        );
+        assert_eq!(pkmn.hp.as_deref(), Some("80"));
+        assert_eq!(pkmn.flavor_text, None);
+        assert_eq!(pkmn.artist.as_deref(), Some("Ken Sugimori"));
+        assert_eq!(pkmn.rarity.as_deref(), Some("Rare"));
+    }
+
+    #[test]
+    fn skip_cards_missing_from_a_side() {
+        let decisions = decisions(json!({"2": {"hp": "accept-right"}, "3": {"hp": "ignore"}}));
+        let (ptcgio, pkmn) = alakazam();
+        let (mut resolved_ptcgio, mut resolved_pkmn) = alakazam();
+        decisions
+            .resolve(
+                "Base",
+                &mut resolved_ptcgio,
+                &mut resolved_pkmn,
+                Resolve::Both,
+            )
+            .unwrap();
+        assert_eq!((resolved_ptcgio, resolved_pkmn), (ptcgio, pkmn));
+    }
+
+    #[test]
+    fn reject_numbers_shared_by_several_cards() {
+        let decisions = decisions(json!({"1": {"hp": "accept-left"}}));
+        let (mut ptcgio, mut pkmn) = alakazam();
+        pkmn.push(Card::stub("base1-1", "Dark Alakazam"));
+        let err = decisions
+            .resolve("Base", &mut ptcgio, &mut pkmn, Resolve::Both)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Decisions for Base #1 are ambiguous as pkmncards has more than one card with that number"
+        );
+    }
+}
@@ -1,4 +1,9 @@
+use crate::ptcgio_data::Card;
+use crate::reconcile::pair_cards;
+use anyhow::{anyhow, bail, Result};
 use itertools::Itertools;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::iter;
 
@@ -27,7 +32,22 @@ impl<'a> ValueDiff<'a> {
 }
 
 pub fn diff<'a>(left: &'a Value, right: &'a Value) -> Box<dyn Iterator<Item = ValueDiff<'a>> + 'a> {
-    diff_(Vec::new(), left, right)
+    diff_(Vec::new(), left, right, ArrayMode::Zip)
+}
+
+/// Like [`diff`] but arrays of different lengths are reported as a single difference instead of
+/// only comparing the elements both sides have, so every difference can be applied on its own.
+pub fn diff_strict<'a>(
+    left: &'a Value,
+    right: &'a Value,
+) -> Box<dyn Iterator<Item = ValueDiff<'a>> + 'a> {
+    diff_(Vec::new(), left, right, ArrayMode::Strict)
+}
+
+#[derive(Clone, Copy)]
+enum ArrayMode {
+    Zip,
+    Strict,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -37,15 +57,16 @@ pub enum ValueIndex {
 }
 
 fn diff_array<'a>(
-    (mut path, (idx, (l, r))): (Vec<ValueIndex>, (usize, (&'a Value, &'a Value))),
+    (mut path, mode, (idx, (l, r))): (Vec<ValueIndex>, ArrayMode, (usize, (&'a Value, &'a Value))),
 ) -> Box<dyn Iterator<Item = ValueDiff<'a>> + 'a> {
     path.push(ValueIndex::Number(idx));
-    diff_(path, l, r)
+    diff_(path, l, r, mode)
 }
 
 fn diff_object<'a>(
-    (mut path, key, l_val, r_val): (
+    (mut path, mode, key, l_val, r_val): (
         Vec<ValueIndex>,
+        ArrayMode,
         &'a String,
         Option<&'a Value>,
         Option<&'a Value>,
@@ -53,7 +74,7 @@ fn diff_object<'a>(
 ) -> Box<dyn Iterator<Item = ValueDiff<'a>> + 'a> {
     path.push(ValueIndex::Key(key.to_string()));
     match (l_val, r_val) {
-        (Some(l_val), Some(r_val)) => diff_(path, l_val, r_val),
+        (Some(l_val), Some(r_val)) => diff_(path, l_val, r_val, mode),
         (Some(val), None) => Box::new(iter::once(ValueDiff::new(path, val, &Value::Null))),
         (None, Some(val)) => Box::new(iter::once(ValueDiff::new(path, &Value::Null, val))),
         (None, None) => Box::new(iter::empty()),
@@ -64,14 +85,20 @@ fn diff_<'a>(
     path: Vec<ValueIndex>,
     left: &'a Value,
     right: &'a Value,
+    mode: ArrayMode,
 ) -> Box<dyn Iterator<Item = ValueDiff<'a>> + 'a> {
     match (left, right) {
+        (Value::Array(l_arr), Value::Array(r_arr))
+            if matches!(mode, ArrayMode::Strict) && l_arr.len() != r_arr.len() =>
+        {
+            Box::new(iter::once(ValueDiff::new(path, left, right)))
+        }
         (Value::Array(l_arr), Value::Array(r_arr)) => Box::new(
             l_arr
                 .iter()
                 .zip(r_arr.iter())
                 .enumerate()
-                .map(move |t| (path.clone(), t))
+                .map(move |t| (path.clone(), mode, t))
                 .flat_map(diff_array),
         ),
         (Value::Object(l_map), Value::Object(r_map)) => Box::new(
@@ -80,7 +107,7 @@ fn diff_<'a>(
                 .chain(r_map.keys())
                 .sorted()
                 .dedup()
-                .map(move |key| (path.clone(), key, l_map.get(key), r_map.get(key)))
+                .map(move |key| (path.clone(), mode, key, l_map.get(key), r_map.get(key)))
                 .flat_map(diff_object),
         ),
         (left, right) => {
@@ -92,3 +119,201 @@ fn diff_<'a>(
         }
     }
 }
+
+/// Renders a path as `attacks[0].damage`
+pub fn path_to_string(path: &[ValueIndex]) -> String {
+    let mut output = String::new();
+    for step in path {
+        match step {
+            ValueIndex::Number(num) => {
+                output.push('[');
+                output.push_str(&num.to_string());
+                output.push(']');
+            }
+            ValueIndex::Key(key) => {
+                if !output.is_empty() {
+                    output.push('.');
+                }
+                output.push_str(key);
+            }
+        }
+    }
+    output
+}
+
+/// Parses a path rendered by [`path_to_string`]
+pub fn parse_path(path: &str) -> Result<Vec<ValueIndex>> {
+    let mut steps = Vec::new();
+    for part in path.split('.') {
+        if part.is_empty() {
+            bail!("Empty step in path \"{}\"", path);
+        }
+        let mut pieces = part.split('[');
+        let key = pieces.next().unwrap_or_default();
+        if !key.is_empty() {
+            steps.push(ValueIndex::Key(key.to_string()));
+        }
+        for index in pieces {
+            let index = index
+                .strip_suffix(']')
+                .ok_or(anyhow!("Malformed index in path \"{}\"", path))?;
+            steps.push(ValueIndex::Number(index.parse().map_err(|err| {
+                anyhow!("Malformed index in path \"{}\": {}", path, err)
+            })?));
+        }
+    }
+    Ok(steps)
+}
+
+pub fn value_at_path<'a>(root: &'a Value, path: &[ValueIndex]) -> Option<&'a Value> {
+    path.iter()
+        .try_fold(root, |value, step| match (step, value) {
+            (ValueIndex::Key(key), Value::Object(map)) => map.get(key),
+            (ValueIndex::Number(idx), Value::Array(arr)) => arr.get(*idx),
+            _ => None,
+        })
+}
+
+/// Replaces the value at `path`. A null value removes an object key rather than storing null,
+/// matching how pokemon-tcg-data leaves out absent fields.
+pub fn set_at_path(root: &mut Value, path: &[ValueIndex], value: Value) -> Result<()> {
+    let (last, parents) = path
+        .split_last()
+        .ok_or(anyhow!("Cannot replace the root of a value"))?;
+    let mut parent = root;
+    for step in parents {
+        parent = match (step, parent) {
+            (ValueIndex::Key(key), Value::Object(map)) => map
+                .get_mut(key)
+                .ok_or(anyhow!("Key {} does not exist", key))?,
+            (ValueIndex::Number(idx), Value::Array(arr)) => arr
+                .get_mut(*idx)
+                .ok_or(anyhow!("Index {} does not exist", idx))?,
+            (step, _) => bail!("Path step {:?} does not match the value's shape", step),
+        };
+    }
+    match (last, parent) {
+        (ValueIndex::Key(key), Value::Object(map)) => {
+            if value.is_null() {
+                // `remove` swaps the last key into the gap, rebuilding keeps the order
+                *map = std::mem::take(map)
+                    .into_iter()
+                    .filter(|(existing, _)| existing != key)
+                    .collect();
+            } else if let Some(slot) = map.get_mut(key) {
+                *slot = value;
+            } else {
+                map.insert(key.to_string(), value);
+            }
+        }
+        (ValueIndex::Number(idx), Value::Array(arr)) => {
+            *arr.get_mut(*idx)
+                .ok_or(anyhow!("Index {} does not exist", idx))? = value;
+        }
+        (step, _) => bail!("Path step {:?} does not match the value's shape", step),
+    }
+    Ok(())
+}
+
+/// A single difference between the ptcgio (left) and pkmncards (right) version of a card
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Eq, PartialEq)]
+pub struct Discrepancy {
+    pub set: String,
+    pub number: String,
+    /// The ptcgio id of the card
+    pub id: String,
+    pub path: String,
+    pub left: Value,
+    pub right: Value,
+}
+
+/// Compares the cards both datasets have for a set, paired up by their number
+pub fn discrepancies(set: &str, ptcgio: &[Card], pkmn: &[Card]) -> Result<Vec<Discrepancy>> {
+    let mut found = Vec::new();
+    for (ptcgio_card, pkmn_card) in pair_cards(ptcgio, pkmn) {
+        let (Some(ptcgio_card), Some(pkmn_card)) = (ptcgio_card, pkmn_card) else {
+            continue;
+        };
+        let left = serde_json::to_value(ptcgio_card)?;
+        let right = serde_json::to_value(pkmn_card)?;
+        found.extend(diff_strict(&left, &right).map(|difference| Discrepancy {
+            set: set.to_string(),
+            number: ptcgio_card.number.clone(),
+            id: ptcgio_card.id.clone(),
+            path: path_to_string(&difference.path),
+            left: difference.left.clone(),
+            right: difference.right.clone(),
+        }));
+    }
+    Ok(found)
+}
+
+impl Discrepancy {
+    /// The top level card field the discrepancy is in
+    pub fn field(&self) -> &str {
+        self.path
+            .split(['.', '['])
+            .next()
+            .unwrap_or(self.path.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn round_trip_paths() {
+        let path = vec![
+            ValueIndex::Key("attacks".to_string()),
+            ValueIndex::Number(1),
+            ValueIndex::Key("cost".to_string()),
+            ValueIndex::Number(0),
+        ];
+        assert_eq!(path_to_string(&path), "attacks[1].cost[0]");
+        assert_eq!(parse_path("attacks[1].cost[0]").unwrap(), path);
+        assert_eq!(
+            parse_path("hp").unwrap(),
+            vec![ValueIndex::Key("hp".to_string())]
+        );
+
+        assert!(parse_path("attacks..cost").is_err());
+        assert!(parse_path("attacks[1").is_err());
+        assert!(parse_path("attacks[one]").is_err());
+    }
+
+    #[test]
+    fn set_values_at_paths() {
+        let mut card =
+            json!({"name": "Alakazam", "hp": "80", "attacks": [{"name": "Confuse Ray"}]});
+
+        set_at_path(&mut card, &parse_path("hp").unwrap(), json!("90")).unwrap();
+        set_at_path(
+            &mut card,
+            &parse_path("attacks[0].damage").unwrap(),
+            json!("30"),
+        )
+        .unwrap();
+        set_at_path(
+            &mut card,
+            &parse_path("artist").unwrap(),
+            json!("Ken Sugimori"),
+        )
+        .unwrap();
+        set_at_path(&mut card, &parse_path("name").unwrap(), Value::Null).unwrap();
+        assert_eq!(
+            serde_json::to_string(&card).unwrap(),
+            r#"{"hp":"90","attacks":[{"name":"Confuse Ray","damage":"30"}],"artist":"Ken Sugimori"}"#
+        );
+
+        assert!(set_at_path(&mut card, &[], json!("Mew")).is_err());
+        assert!(set_at_path(
+            &mut card,
+            &parse_path("attacks[1].damage").unwrap(),
+            json!("")
+        )
+        .is_err());
+        assert!(set_at_path(&mut card, &parse_path("hp.value").unwrap(), json!("")).is_err());
+    }
+}
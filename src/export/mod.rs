@@ -0,0 +1,409 @@
+use crate::ptcgio_data::damage::Modifier;
+use crate::ptcgio_data::{Card, Damage};
+use anyhow::{Context, Result};
+use itertools::Itertools;
+use parquet::basic::Compression;
+use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use serde_json::Value;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default, clap::ValueEnum, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Parquet,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Kind {
+    Text,
+    Integer,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Cell {
+    Text(String),
+    Integer(i64),
+    Null,
+}
+
+impl From<Option<&String>> for Cell {
+    fn from(text: Option<&String>) -> Self {
+        text.map_or(Cell::Null, |text| Cell::Text(text.clone()))
+    }
+}
+
+/// One flat file of the export, the cards or one of the lists cards have
+#[derive(Debug)]
+pub struct Table {
+    name: &'static str,
+    columns: Vec<(&'static str, Kind)>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    fn new(name: &'static str, columns: Vec<(&'static str, Kind)>) -> Self {
+        Table {
+            name,
+            columns,
+            rows: Vec::new(),
+        }
+    }
+}
+
+/// Lists in a cell are joined with this
+const SEPARATOR: &str = "; ";
+
+fn joined(list: &Option<Vec<String>>, separator: &str) -> Cell {
+    list.as_ref()
+        .map_or(Cell::Null, |list| Cell::Text(list.join(separator)))
+}
+
+/// The cards of `sets` as one row each in `cards`, with their attacks, abilities, weaknesses
+/// and resistances in tables of their own keyed by `card_id`
+pub fn tables(sets: &[(String, Vec<Card>)]) -> Vec<Table> {
+    use Kind::*;
+    let mut cards = Table::new(
+        "cards",
+        vec![
+            ("id", Text),
+            ("set", Text),
+            ("number", Text),
+            ("name", Text),
+            ("supertype", Text),
+            ("subtypes", Text),
+            ("level", Text),
+            ("hp", Text),
+            ("types", Text),
+            ("evolves_from", Text),
+            ("evolves_to", Text),
+            ("rules", Text),
+            ("ancient_trait_name", Text),
+            ("ancient_trait_text", Text),
+            ("retreat_cost", Text),
+            ("converted_retreat_cost", Integer),
+            ("artist", Text),
+            ("rarity", Text),
+            ("flavor_text", Text),
+            ("national_pokedex_numbers", Text),
+            ("regulation_mark", Text),
+            ("legalities", Text),
+        ],
+    );
+    let mut attacks = Table::new(
+        "attacks",
+        vec![
+            ("card_id", Text),
+            ("position", Integer),
+            ("name", Text),
+            ("cost", Text),
+            ("converted_energy_cost", Integer),
+            ("damage", Text),
+            ("damage_base", Integer),
+            ("damage_modifier", Text),
+            ("text", Text),
+        ],
+    );
+    let mut abilities = Table::new(
+        "abilities",
+        vec![
+            ("card_id", Text),
+            ("position", Integer),
+            ("name", Text),
+            ("type", Text),
+            ("text", Text),
+        ],
+    );
+    let modifier_columns = vec![("card_id", Text), ("type", Text), ("value", Text)];
+    let mut weaknesses = Table::new("weaknesses", modifier_columns.clone());
+    let mut resistances = Table::new("resistances", modifier_columns);
+
+    for (set, set_cards) in sets {
+        for card in set_cards {
+            let id = Cell::Text(card.id.clone());
+            let ancient_trait = |key: &str| {
+                Cell::from(
+                    card.ancient_trait
+                        .as_ref()
+                        .and_then(|ancient_trait| ancient_trait.get(key)),
+                )
+            };
+            cards.rows.push(vec![
+                id.clone(),
+                Cell::Text(set.clone()),
+                Cell::Text(card.number.clone()),
+                Cell::Text(card.name.clone()),
+                Cell::Text(card.supertype.clone()),
+                joined(&card.subtypes, SEPARATOR),
+                Cell::from(card.level.as_ref()),
+                Cell::from(card.hp.as_ref()),
+                joined(&card.types, SEPARATOR),
+                Cell::from(card.evolves_from.as_ref()),
+                joined(&card.evolves_to, SEPARATOR),
+                joined(&card.rules, "\n"),
+                ancient_trait("name"),
+                ancient_trait("text"),
+                joined(&card.retreat_cost, SEPARATOR),
+                card.converted_retreat_cost
+                    .map_or(Cell::Null, |cost| Cell::Integer(cost as i64)),
+                Cell::from(card.artist.as_ref()),
+                Cell::from(card.rarity.as_ref()),
+                Cell::from(card.flavor_text.as_ref()),
+                card.national_pokedex_numbers
+                    .as_ref()
+                    .map_or(Cell::Null, |numbers| {
+                        Cell::Text(numbers.iter().join(SEPARATOR))
+                    }),
+                Cell::from(card.regulation_mark.as_ref()),
+                Cell::Text(
+                    card.legalities
+                        .iter()
+                        .map(|(format, legality)| format!("{}: {}", format, legality))
+                        .join(SEPARATOR),
+                ),
+            ]);
+
+            let damages = card.attack_damages();
+            for (position, attack) in card.attacks.iter().flatten().enumerate() {
+                let text = |key: &str| match attack.get(key) {
+                    Some(Value::String(text)) => Cell::Text(text.clone()),
+                    _ => Cell::Null,
+                };
+                let cost = match attack.get("cost") {
+                    Some(Value::Array(cost)) => Cell::Text(
+                        cost.iter()
+                            .filter_map(|energy| energy.as_str())
+                            .join(SEPARATOR),
+                    ),
+                    _ => Cell::Null,
+                };
+                let (damage_base, damage_modifier) = match &damages[position] {
+                    Some(Damage::Value { base, modifier }) => (
+                        Cell::Integer(*base as i64),
+                        match modifier {
+                            Modifier::None => Cell::Null,
+                            modifier => Cell::Text(modifier.to_string()),
+                        },
+                    ),
+                    Some(Damage::Other(_)) | None => (Cell::Null, Cell::Null),
+                };
+                attacks.rows.push(vec![
+                    id.clone(),
+                    Cell::Integer(position as i64),
+                    text("name"),
+                    cost,
+                    attack
+                        .get("convertedEnergyCost")
+                        .and_then(Value::as_i64)
+                        .map_or(Cell::Null, Cell::Integer),
+                    text("damage"),
+                    damage_base,
+                    damage_modifier,
+                    text("text"),
+                ]);
+            }
+            for (position, ability) in card.abilities.iter().flatten().enumerate() {
+                abilities.rows.push(vec![
+                    id.clone(),
+                    Cell::Integer(position as i64),
+                    Cell::from(ability.get("name")),
+                    Cell::from(ability.get("type")),
+                    Cell::from(ability.get("text")),
+                ]);
+            }
+            for (table, modifiers) in [
+                (&mut weaknesses, &card.weaknesses),
+                (&mut resistances, &card.resistances),
+            ] {
+                table
+                    .rows
+                    .extend(modifiers.iter().flatten().map(|modifier| {
+                        vec![
+                            id.clone(),
+                            Cell::from(modifier.get("type")),
+                            Cell::from(modifier.get("value")),
+                        ]
+                    }));
+            }
+        }
+    }
+    vec![cards, attacks, abilities, weaknesses, resistances]
+}
+
+/// Writes every table to `<table>.csv` or `<table>.parquet` in `dir`
+pub fn write(tables: &[Table], format: ExportFormat, dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    for table in tables {
+        let path = dir.join(table.name).with_extension(format.to_string());
+        match format {
+            ExportFormat::Csv => write_csv(table, &path),
+            ExportFormat::Parquet => write_parquet(table, &path),
+        }
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn write_csv(table: &Table, path: &Path) -> Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(table.columns.iter().map(|(name, _)| name))?;
+    for row in &table.rows {
+        writer.write_record(row.iter().map(|cell| match cell {
+            Cell::Text(text) => text.clone(),
+            Cell::Integer(integer) => integer.to_string(),
+            Cell::Null => String::new(),
+        }))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the table as a single, snappy compressed row group with every column optional
+fn write_parquet(table: &Table, path: &Path) -> Result<()> {
+    let fields = table
+        .columns
+        .iter()
+        .map(|(name, kind)| match kind {
+            Kind::Text => format!("OPTIONAL BYTE_ARRAY {} (UTF8);", name),
+            Kind::Integer => format!("OPTIONAL INT64 {};", name),
+        })
+        .join(" ");
+    let schema = parse_message_type(&format!("message {} {{ {} }}", table.name, fields))?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer =
+        SerializedFileWriter::new(File::create(path)?, Arc::new(schema), Arc::new(properties))?;
+
+    let mut row_group = writer.next_row_group()?;
+    let mut index = 0;
+    while let Some(mut column) = row_group.next_column()? {
+        let cells = table.rows.iter().map(|row| &row[index]);
+        let definitions = cells
+            .clone()
+            .map(|cell| i16::from(*cell != Cell::Null))
+            .collect_vec();
+        match table.columns[index].1 {
+            Kind::Text => {
+                let values = cells
+                    .filter_map(|cell| match cell {
+                        Cell::Text(text) => Some(ByteArray::from(text.as_str())),
+                        _ => None,
+                    })
+                    .collect_vec();
+                column
+                    .typed::<ByteArrayType>()
+                    .write_batch(&values, Some(&definitions), None)?;
+            }
+            Kind::Integer => {
+                let values = cells
+                    .filter_map(|cell| match cell {
+                        Cell::Integer(integer) => Some(*integer),
+                        _ => None,
+                    })
+                    .collect_vec();
+                column
+                    .typed::<Int64Type>()
+                    .write_batch(&values, Some(&definitions), None)?;
+            }
+        }
+        column.close()?;
+        index += 1;
+    }
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn sets() -> Vec<(String, Vec<Card>)> {
+        let card = Card {
+            subtypes: Some(vec!["Basic".to_string()]),
+            hp: Some("40".to_string()),
+            attacks: Some(vec![BTreeMap::from([
+                ("name".to_string(), json!("Gnaw")),
+                ("cost".to_string(), json!(["Colorless"])),
+                ("convertedEnergyCost".to_string(), json!(1)),
+                ("damage".to_string(), json!("10")),
+                ("text".to_string(), json!("")),
+            ])]),
+            weaknesses: Some(vec![BTreeMap::from([
+                ("type".to_string(), "Fighting".to_string()),
+                ("value".to_string(), "×2".to_string()),
+            ])]),
+            converted_retreat_cost: Some(1),
+            legalities: BTreeMap::from([("unlimited".to_string(), "Legal".to_string())]),
+            ..Card::stub("base1-58", "Pikachu")
+        };
+        let energy = Card {
+            supertype: "Energy".to_string(),
+            ..Card::stub("base1-102", "Water Energy")
+        };
+        vec![("Base".to_string(), vec![card, energy])]
+    }
+
+    #[test]
+    fn flatten_cards() {
+        let tables = tables(&sets());
+        let sizes = tables
+            .iter()
+            .map(|table| (table.name, table.rows.len()))
+            .collect_vec();
+        assert_eq!(
+            sizes,
+            vec![
+                ("cards", 2),
+                ("attacks", 1),
+                ("abilities", 0),
+                ("weaknesses", 1),
+                ("resistances", 0)
+            ]
+        );
+        assert_eq!(
+            tables[1].rows[0],
+            vec![
+                Cell::Text("base1-58".to_string()),
+                Cell::Integer(0),
+                Cell::Text("Gnaw".to_string()),
+                Cell::Text("Colorless".to_string()),
+                Cell::Integer(1),
+                Cell::Text("10".to_string()),
+                Cell::Integer(10),
+                Cell::Null,
+                Cell::Text(String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn write_csv_and_parquet() {
+        let dir = tempfile::tempdir().unwrap();
+        let tables = tables(&sets());
+        write(&tables, ExportFormat::Csv, dir.path()).unwrap();
+        write(&tables, ExportFormat::Parquet, dir.path()).unwrap();
+
+        let csv = std::fs::read_to_string(dir.path().join("weaknesses.csv")).unwrap();
+        assert_eq!(csv, "card_id,type,value\nbase1-58,Fighting,×2\n");
+
+        let cards = File::open(dir.path().join("cards.parquet")).unwrap();
+        let reader = SerializedFileReader::new(cards).unwrap();
+        let metadata = reader.metadata().file_metadata();
+        assert_eq!(metadata.num_rows(), 2);
+        assert_eq!(metadata.schema_descr().num_columns(), 22);
+        let empty = File::open(dir.path().join("abilities.parquet")).unwrap();
+        let reader = SerializedFileReader::new(empty).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 0);
+    }
+}
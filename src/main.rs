@@ -1,45 +1,38 @@
+mod database;
+mod decisions;
 mod diff;
+mod export;
 mod mapping;
+mod patch;
 mod pkmn_data;
 mod ptcgio_data;
+mod query;
+mod reconcile;
+mod search;
+mod serve;
 
-use crate::diff::ValueIndex;
-use crate::mapping::{DatasetMappings, MappingOperations};
+use crate::database::{Database, Dataset, Sets};
+use crate::decisions::{Decisions, Resolve};
+use crate::diff::{Discrepancy, ValueIndex};
+use crate::mapping::{DatasetMappings, MappingOperations, MappingSuggestions, SetProfile};
 use crate::ptcgio_data::Card;
-use anyhow::{Context, Result};
+use crate::query::{FieldPath, Query, Subject};
+use crate::reconcile::Policy;
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
 use heck::ToSnekCase;
 use itertools::Itertools;
+use schemars::{schema_for, JsonSchema};
+use serde::Serialize;
 use serde_json::Value;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashMap, HashSet};
-use std::path::PathBuf;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use tokio::fs::File;
 use tokio::io::{AsyncWriteExt, BufWriter};
 
-async fn filtering_example(data: ptcgio_data::Data) {
-    let filtered_data = data
-        .sets
-        .iter()
-        .flat_map(|set| {
-            set.cards.iter().filter(|card| {
-                card.resistances.as_ref().is_some_and(|resistances| {
-                    resistances.iter().any(|map| {
-                        map.get("value")
-                            .is_some_and(|value| value != "-30" && value != "-20")
-                    })
-                }) || card.weaknesses.as_ref().is_some_and(|resistances| {
-                    resistances
-                        .iter()
-                        .any(|map| map.get("value").is_some_and(|value| value != "×2"))
-                })
-            })
-        })
-        .collect::<Vec<&Card>>();
-
-    println!("{:#?}\n{}", filtered_data, filtered_data.len());
-}
-
 fn extract_unique_sets(sets: HashMap<String, Vec<Vec<Card>>>) -> BTreeMap<String, Vec<Card>> {
     sets.into_iter()
         .map(|set| {
@@ -71,26 +64,26 @@ fn extract_unique_sets(sets: HashMap<String, Vec<Vec<Card>>>) -> BTreeMap<String
 async fn process_dataset(
     dataset_iter: impl Iterator<Item = pkmn_data::Set>,
     mapping_operations: &MappingOperations,
+    dry_run: bool,
 ) -> Result<BTreeMap<String, Vec<Card>>> {
     let data = dataset_iter
-        .map(|set| {
-            (
-                set.name.clone(),
-                set.cards
-                    .iter()
-                    .map(|card| {
-                        let mut new_card = card.clone();
-                        new_card.images.clear();
-                        new_card.legalities.clear();
-                        new_card.national_pokedex_numbers = None;
-                        new_card
-                    })
-                    .collect::<Vec<Card>>(),
-            )
-        })
+        .map(|set| (set.name, set.cards))
         .into_group_map();
     let data = extract_unique_sets(data);
-    mapping_operations.map(data).await
+    if dry_run {
+        mapping_operations.dry_run(data).await
+    } else {
+        mapping_operations.map(data).await
+    }
+}
+
+/// Clears the fields only one of the datasets has data for so they don't show up in every diff
+fn strip_one_sided_fields(mut card: Card) -> Card {
+    card.enriched.clear();
+    card.images.clear();
+    card.legalities.clear();
+    card.national_pokedex_numbers = None;
+    card
 }
 
 async fn write_set_file(set: &HashSet<String>, file: File) -> Result<usize> {
@@ -102,45 +95,852 @@ async fn write_set_file(set: &HashSet<String>, file: File) -> Result<usize> {
     Ok(ret)
 }
 
+#[derive(Parser)]
+#[command(about = "Compares the pokemon-tcg-data dataset against pkmncards.com")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    http: pkmn_data::HttpOptions,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write the set and card diffs and the reconciled dataset (the default)
+    Diff,
+    /// Correct a local pokemon-tcg-data checkout with the values pkmncards has
+    Patch {
+        /// The pokemon-tcg-data checkout to rewrite
+        checkout: PathBuf,
+        /// The card fields pkmncards is considered correct for
+        #[arg(
+            long,
+            value_delimiter = ',',
+            default_value = "abilities,ancientTrait,attacks,flavorText,rules"
+        )]
+        fields: Vec<String>,
+        /// Commit the rewritten files in the checkout
+        #[arg(long)]
+        commit: bool,
+    },
+    /// Check the mapping file against the sets both datasets have
+    ValidateMapping {
+        /// A pokemon-tcg-data checkout to use instead of cloning it
+        #[arg(long)]
+        checkout: Option<PathBuf>,
+    },
+    /// Print what every mapping operation does and the resulting sets of both datasets
+    DryRunMapping {
+        /// A pokemon-tcg-data checkout to use instead of cloning it
+        #[arg(long)]
+        checkout: Option<PathBuf>,
+    },
+    /// Suggest map operations for the sets only one of the datasets has
+    ///
+    /// Pairs released close together score higher, but only pkmncards sets fetched since release
+    /// dates were kept have one. The sets checked into `pkmn_data` are scored without their date
+    /// until they are deleted from the store and fetched again.
+    SuggestMappings {
+        /// A pokemon-tcg-data checkout to use instead of cloning it
+        #[arg(long)]
+        checkout: Option<PathBuf>,
+    },
+    /// Fetch pkmncards into a store of its own with the card text written in another format
+    Fetch {
+        /// How the text of attacks, abilities and rules is written
+        #[arg(long, value_enum, default_value_t)]
+        text_format: pkmn_data::TextFormat,
+    },
+    /// Rewrite the golden card of every page in the fixture corpus after intended parser changes
+    BlessFixtures {
+        /// The directory of card pages
+        #[arg(default_value = pkmn_data::FIXTURES)]
+        dir: PathBuf,
+    },
+    /// Import both datasets, and the merged one if the diff wrote it, into a SQLite database
+    ExportSqlite {
+        /// The database to write, datasets already in it are replaced
+        #[arg(default_value = "pkmn.sqlite")]
+        database: PathBuf,
+        /// The pkmncards store to import, nothing is fetched
+        #[arg(long, default_value = "pkmn_data")]
+        store: PathBuf,
+        /// A pokemon-tcg-data checkout to use instead of cloning it
+        #[arg(long)]
+        checkout: Option<PathBuf>,
+    },
+    /// Print the cards of a dataset a query matches, like
+    /// `supertype = "Pokémon" and weaknesses.value != "×2" and set in ["Base", "Jungle"]`
+    Query {
+        query: Query,
+        #[arg(long, value_enum, default_value_t = Dataset::Ptcgio)]
+        dataset: Dataset,
+        #[arg(long, value_enum, default_value_t)]
+        format: OutputFormat,
+        /// The columns of tables and CSV
+        #[arg(long, value_delimiter = ',', default_value = "set,number,name")]
+        fields: Vec<FieldPath>,
+        /// The pkmncards store to query, nothing is fetched
+        #[arg(long, default_value = "pkmn_data")]
+        store: PathBuf,
+        /// A pokemon-tcg-data checkout to use instead of cloning it
+        #[arg(long)]
+        checkout: Option<PathBuf>,
+    },
+    /// Write flat files of the cards of datasets, with their attacks, abilities, weaknesses and
+    /// resistances in tables of their own, to `<out_dir>/<dataset>/`
+    Export {
+        #[arg(long, value_enum, default_value_t)]
+        format: export::ExportFormat,
+        /// The datasets to export, by default both and the merged one if the diff wrote it
+        #[arg(long, value_enum, value_delimiter = ',')]
+        datasets: Vec<Dataset>,
+        #[arg(long, default_value = "exports")]
+        out_dir: PathBuf,
+        /// The pkmncards store to export, nothing is fetched
+        #[arg(long, default_value = "pkmn_data")]
+        store: PathBuf,
+        /// A pokemon-tcg-data checkout to use instead of cloning it
+        #[arg(long)]
+        checkout: Option<PathBuf>,
+    },
+    /// Serve the datasets and the diff's discrepancies read-only over a local JSON API
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        address: SocketAddr,
+        /// The pkmncards store to serve, nothing is fetched
+        #[arg(long, default_value = "pkmn_data")]
+        store: PathBuf,
+        /// A pokemon-tcg-data checkout to use instead of cloning it
+        #[arg(long)]
+        checkout: Option<PathBuf>,
+    },
+    /// Search the cards of the datasets by their name, attack and ability texts, rules and flavor
+    /// text, best matches first
+    Search {
+        /// In tantivy's query syntax, `"search your deck"` matches the phrase
+        text: String,
+        /// The datasets to search, by default all of them
+        #[arg(long, value_enum, value_delimiter = ',')]
+        datasets: Vec<Dataset>,
+        #[arg(long)]
+        set: Option<String>,
+        #[arg(long)]
+        supertype: Option<String>,
+        /// Keep the cards with any of the types
+        #[arg(long, value_delimiter = ',')]
+        types: Vec<String>,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        #[arg(long, value_enum, default_value_t)]
+        format: OutputFormat,
+        /// The index, built from both datasets and the merged one the first time it's searched
+        #[arg(long, default_value = "search-index")]
+        index: PathBuf,
+        /// Build the index again, once the datasets changed
+        #[arg(long)]
+        rebuild: bool,
+        /// The pkmncards store to index, nothing is fetched
+        #[arg(long, default_value = "pkmn_data")]
+        store: PathBuf,
+        /// A pokemon-tcg-data checkout to use instead of cloning it
+        #[arg(long)]
+        checkout: Option<PathBuf>,
+    },
+    /// Write the JSON Schemas of the mapping file and the diff outputs
+    Schema {
+        /// The directory to write the schemas to
+        #[arg(default_value = "schemas")]
+        out_dir: PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, Default, clap::ValueEnum)]
+enum OutputFormat {
+    /// The matching cards in full, with their set
+    #[default]
+    Json,
+    Table,
+    Csv,
+}
+
+const PERSONAL_DATA_URL: &str = "https://github.com/ProfDoof/pokemon-tcg-data.git";
+const SET_OPERATIONS: &str = "mapping/set_operations.json";
+
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
-    let mapping_operations = DatasetMappings::load("mapping/set_operations.json").await?;
-    const PERSONAL_DATA_URL: &str = "https://github.com/ProfDoof/pokemon-tcg-data.git";
-    let mut ptcgio_data = ptcgio_data::DataFetcher::new(PERSONAL_DATA_URL, "ptcg-data").fetch()?;
+    let cli = Cli::parse();
+    let http = &cli.http;
+    match cli.command.unwrap_or(Command::Diff) {
+        Command::Diff => {
+            let mapping_operations = DatasetMappings::load(SET_OPERATIONS).await?;
+            let mut ptcgio_data =
+                ptcgio_data::DataFetcher::new(PERSONAL_DATA_URL, "ptcg-data").fetch()?;
+            let (ptcgio_sets, pkmn_sets) =
+                load_datasets(&mut ptcgio_data, &mapping_operations, http, false).await?;
+            run_diff(
+                &ptcgio_data,
+                ptcgio_sets,
+                pkmn_sets,
+                &load_decisions().await?,
+            )
+            .await
+        }
+        Command::Patch {
+            checkout,
+            fields,
+            commit,
+        } => {
+            let mapping_operations = DatasetMappings::load(SET_OPERATIONS).await?;
+            let mut ptcgio_data = ptcgio_data::Data::open(&checkout)?;
+            let (ptcgio_sets, pkmn_sets) =
+                load_datasets(&mut ptcgio_data, &mapping_operations, http, false).await?;
+            run_patch(
+                &ptcgio_data,
+                ptcgio_sets,
+                pkmn_sets,
+                &load_decisions().await?,
+                &fields,
+                commit,
+            )
+            .await
+        }
+        Command::ValidateMapping { checkout } => {
+            let mapping_operations = DatasetMappings::load(SET_OPERATIONS).await?;
+            let ptcgio_data = match checkout {
+                Some(checkout) => ptcgio_data::Data::open(checkout)?,
+                None => ptcgio_data::DataFetcher::new(PERSONAL_DATA_URL, "ptcg-data").fetch()?,
+            };
+            validate_mapping(&mapping_operations, &ptcgio_data, http).await?;
+            println!("The mapping file is valid");
+            Ok(())
+        }
+        Command::DryRunMapping { checkout } => {
+            let mapping_operations = DatasetMappings::load(SET_OPERATIONS).await?;
+            let mut ptcgio_data = match checkout {
+                Some(checkout) => ptcgio_data::Data::open(checkout)?,
+                None => ptcgio_data::DataFetcher::new(PERSONAL_DATA_URL, "ptcg-data").fetch()?,
+            };
+            let (ptcgio_sets, pkmn_sets) =
+                load_datasets(&mut ptcgio_data, &mapping_operations, http, true).await?;
+            print_set_comparison(&ptcgio_sets, &pkmn_sets);
+            Ok(())
+        }
+        Command::SuggestMappings { checkout } => {
+            let mapping_operations = DatasetMappings::load(SET_OPERATIONS).await?;
+            let mut ptcgio_data = match checkout {
+                Some(checkout) => ptcgio_data::Data::open(checkout)?,
+                None => ptcgio_data::DataFetcher::new(PERSONAL_DATA_URL, "ptcg-data").fetch()?,
+            };
+            let (ptcgio_sets, pkmn_sets) =
+                load_datasets(&mut ptcgio_data, &mapping_operations, http, false).await?;
+            suggest_mappings(&ptcgio_data, &ptcgio_sets, &pkmn_sets, http).await
+        }
+        Command::Fetch { text_format } => fetch_pkmn_data(text_format, http.clone()).await,
+        Command::BlessFixtures { dir } => {
+            let blessed = pkmn_data::bless(&dir).await?;
+            println!("Blessed {} fixtures in {}", blessed, dir.display());
+            Ok(())
+        }
+        Command::ExportSqlite {
+            database,
+            store,
+            checkout,
+        } => export_sqlite(&database, &store, checkout.as_deref()).await,
+        Command::Query {
+            query,
+            dataset,
+            format,
+            fields,
+            store,
+            checkout,
+        } => {
+            let sets = load_dataset(dataset, checkout.as_deref(), &store).await?;
+            print_query_matches(&query, &sets, format, &fields)
+        }
+        Command::Export {
+            format,
+            datasets,
+            out_dir,
+            store,
+            checkout,
+        } => {
+            let datasets = if datasets.is_empty() {
+                available_datasets()
+            } else {
+                datasets
+            };
+            for dataset in datasets {
+                let sets = load_dataset(dataset, checkout.as_deref(), &store).await?;
+                let dir = out_dir.join(dataset.to_string());
+                export::write(&export::tables(&sets), format, &dir)?;
+                println!("Exported {} to {}", dataset, dir.display());
+            }
+            Ok(())
+        }
+        Command::Serve {
+            address,
+            store,
+            checkout,
+        } => {
+            let mut datasets = vec![(
+                Dataset::Ptcgio,
+                load_dataset(Dataset::Ptcgio, checkout.as_deref(), &store).await?,
+            )];
+            let pkmn_data = pkmn_data::Data::open(&store).await?;
+            let pkmn_sets = pkmn_data
+                .all_series
+                .into_iter()
+                .flat_map(|series| series.sets)
+                .map(|set| (set.name, set.cards))
+                .collect();
+            datasets.push((Dataset::Pkmn, pkmn_sets));
+            if Path::new("merged").exists() {
+                let merged = load_dataset(Dataset::Merged, None, &store).await?;
+                datasets.push((Dataset::Merged, merged));
+            }
+            let diffs = serve::load_diffs(Path::new("diffs")).await?;
+            serve::serve(serve::Api::new(datasets, diffs), address).await
+        }
+        Command::Search {
+            text,
+            datasets,
+            set,
+            supertype,
+            types,
+            limit,
+            format,
+            index,
+            rebuild,
+            store,
+            checkout,
+        } => {
+            let index = if rebuild || !index.exists() {
+                let mut sets = Vec::new();
+                for dataset in available_datasets() {
+                    sets.push((
+                        dataset,
+                        load_dataset(dataset, checkout.as_deref(), &store).await?,
+                    ));
+                }
+                let built = search::SearchIndex::build(&index, &sets)?;
+                eprintln!(
+                    "Indexed {} into {}",
+                    sets.iter().map(|(d, _)| d).join(", "),
+                    index.display()
+                );
+                built
+            } else {
+                search::SearchIndex::open(&index)?
+            };
+            let filters = search::Filters {
+                datasets,
+                set,
+                supertype,
+                types,
+            };
+            print_search_hits(&index.search(&text, &filters, limit)?, format)
+        }
+        Command::Schema { out_dir } => write_schemas(&out_dir).await,
+    }
+}
+
+/// The decisions reviewers made, none before the first one is written down
+async fn load_decisions() -> Result<Decisions> {
+    let decisions_path = Path::new("mapping/decisions.json");
+    if decisions_path.exists() {
+        Decisions::load(decisions_path).await
+    } else {
+        Ok(Decisions::default())
+    }
+}
+
+/// Fetches pkmncards into `pkmn_data`, or `pkmn_data_<format>` for text that isn't plain so
+/// the store the diffs read stays plain
+async fn fetch_pkmn_data(
+    text_format: pkmn_data::TextFormat,
+    http: pkmn_data::HttpOptions,
+) -> Result<()> {
+    let store_path = match text_format {
+        pkmn_data::TextFormat::Plain => PathBuf::from("pkmn_data"),
+        text_format => PathBuf::from(format!("pkmn_data_{}", text_format)),
+    };
+    let data = pkmn_data::DataFetcher::new(pkmn_data::SETS_URL, &store_path)
+        .with_text_format(text_format)
+        .with_http(http)
+        .fetch()
+        .await?;
+    let sets = data
+        .all_series
+        .iter()
+        .map(|series| series.sets.len())
+        .sum::<usize>();
+    println!("Fetched {} sets into {}", sets, store_path.display());
+    Ok(())
+}
+
+/// The sets of a dataset as they are, before any mapping, by name. Pkmncards is read from the
+/// sets already in `store`, nothing is fetched.
+async fn load_dataset(dataset: Dataset, checkout: Option<&Path>, store: &Path) -> Result<Sets> {
+    let mut ptcgio_data = match dataset {
+        Dataset::Pkmn => {
+            let pkmn_data = pkmn_data::Data::open(store).await?;
+            return Ok(pkmn_data
+                .all_series
+                .into_iter()
+                .flat_map(|series| series.sets)
+                .map(|set| (set.name, set.cards))
+                .collect());
+        }
+        Dataset::Ptcgio => match checkout {
+            Some(checkout) => ptcgio_data::Data::open(checkout)?,
+            None => ptcgio_data::DataFetcher::new(PERSONAL_DATA_URL, "ptcg-data").fetch()?,
+        },
+        Dataset::Merged => ptcgio_data::Data::open("merged")
+            .context("There is no merged dataset, the diff writes it")?,
+    };
+    Ok(ptcgio_data
+        .sets
+        .iter_mut()
+        .map(|set| (set.name.clone(), std::mem::take(&mut set.cards)))
+        .collect())
+}
+
+/// Prints the cards `query` matches as JSON, or their `fields` as a table or CSV
+fn print_query_matches(
+    query: &Query,
+    sets: &Sets,
+    format: OutputFormat,
+    fields: &[FieldPath],
+) -> Result<()> {
+    let mut matches = Vec::new();
+    for (set, cards) in sets {
+        for card in cards {
+            let subject = Subject::new(set, card)?;
+            if query.matches(&subject) {
+                matches.push(subject);
+            }
+        }
+    }
+
+    let rows = matches
+        .iter()
+        .map(|subject| fields.iter().map(|field| subject.text(field)).collect_vec())
+        .collect_vec();
+    let header = fields.iter().map(|field| field.to_string()).collect_vec();
+    match format {
+        OutputFormat::Json => {
+            let cards = matches.iter().map(Subject::fields).collect_vec();
+            println!("{}", serde_json::to_string_pretty(&cards)?);
+        }
+        OutputFormat::Csv | OutputFormat::Table => print_rows(format, &header, &rows)?,
+    }
+    Ok(())
+}
+
+/// Prints the hits as JSON, or their score and card as a table or CSV
+fn print_search_hits(hits: &[search::Hit], format: OutputFormat) -> Result<()> {
+    if let OutputFormat::Json = format {
+        println!("{}", serde_json::to_string_pretty(hits)?);
+        return Ok(());
+    }
+    let header = ["score", "dataset", "set", "number", "name"].map(String::from);
+    let rows = hits
+        .iter()
+        .map(|hit| {
+            vec![
+                format!("{:.2}", hit.score),
+                hit.dataset.to_string(),
+                hit.set.clone(),
+                hit.card.number.clone(),
+                hit.card.name.clone(),
+            ]
+        })
+        .collect_vec();
+    print_rows(format, &header, &rows)
+}
+
+/// Prints rows as CSV or as a table with aligned columns, the row count going to stderr
+fn print_rows(format: OutputFormat, header: &[String], rows: &[Vec<String>]) -> Result<()> {
+    if let OutputFormat::Csv = format {
+        let mut writer = csv::Writer::from_writer(std::io::stdout());
+        writer.write_record(header)?;
+        for row in rows {
+            writer.write_record(row)?;
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+    let widths = rows.iter().fold(
+        header.iter().map(|cell| cell.chars().count()).collect_vec(),
+        |widths, row| {
+            widths
+                .into_iter()
+                .zip(row)
+                .map(|(width, cell)| width.max(cell.chars().count()))
+                .collect()
+        },
+    );
+    for row in std::iter::once(header).chain(rows.iter().map(Vec::as_slice)) {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{}{}", cell, " ".repeat(width - cell.chars().count())))
+            .join(" | ");
+        println!("{}", line.trim_end());
+    }
+    eprintln!("{} cards", rows.len());
+    Ok(())
+}
+
+/// Both datasets, and the merged one once the diff wrote it
+fn available_datasets() -> Vec<Dataset> {
+    let mut datasets = vec![Dataset::Ptcgio, Dataset::Pkmn];
+    if Path::new("merged").exists() {
+        datasets.push(Dataset::Merged);
+    }
+    datasets
+}
+
+/// Imports the datasets as they are, before any mapping, so every set of both keeps its series
+async fn export_sqlite(path: &Path, store: &Path, checkout: Option<&Path>) -> Result<()> {
+    let mut database = Database::open(path)?;
 
-    let ptcgio_data = process_dataset(
+    let (ptcgio_data, origin) = match checkout {
+        Some(checkout) => (
+            ptcgio_data::Data::open(checkout)?,
+            checkout.display().to_string(),
+        ),
+        None => (
+            ptcgio_data::DataFetcher::new(PERSONAL_DATA_URL, "ptcg-data").fetch()?,
+            PERSONAL_DATA_URL.to_string(),
+        ),
+    };
+    let cards = database.import_sets(Dataset::Ptcgio, &origin, &ptcgio_data.sets)?;
+    println!("Imported {} ptcgio cards", cards);
+
+    let pkmn_data = pkmn_data::Data::open(store).await?;
+    let cards = database.import_series(&store.display().to_string(), &pkmn_data.all_series)?;
+    println!("Imported {} pkmn cards", cards);
+
+    let merged_dir = Path::new("merged");
+    if merged_dir.exists() {
+        let merged = ptcgio_data::Data::open(merged_dir)?;
+        let cards = database.import_sets(Dataset::Merged, "merged", &merged.sets)?;
+        println!("Imported {} merged cards", cards);
+    }
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// Writes a schema for every file editors or downstream consumers deal with, named after the
+/// file it describes
+async fn write_schemas(out_dir: &Path) -> Result<()> {
+    tokio::fs::create_dir_all(out_dir).await?;
+    let schemas = [
+        ("set_operations", schema_for!(DatasetMappings)),
+        ("discrepancies", schema_for!(Vec<Discrepancy>)),
+        ("data", schema_for!(Vec<MergedCards>)),
+        ("set_list", schema_for!(Vec<String>)),
+        ("suggested_mappings", schema_for!(MappingSuggestions)),
+    ];
+    for (name, schema) in schemas {
+        let mut output = serde_json::to_vec_pretty(&schema)?;
+        output.push(b'\n');
+        let path = out_dir.join(name).with_extension("schema.json");
+        tokio::fs::write(&path, output)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Scores the sets only one side has against each other, the ones `diffs/sets/ptcgio_only.json`
+/// and `pkmn_only.json` list, and writes the likely pairs to
+/// `diffs/sets/suggested_mappings.json`
+async fn suggest_mappings(
+    ptcgio_data: &ptcgio_data::Data,
+    ptcgio_sets: &BTreeMap<String, Vec<Card>>,
+    pkmn_sets: &BTreeMap<String, Vec<Card>>,
+    http: &pkmn_data::HttpOptions,
+) -> Result<()> {
+    let ptcgio_only = ptcgio_sets
+        .iter()
+        .filter(|(name, _)| !pkmn_sets.contains_key(*name))
+        .map(|(name, cards)| {
+            let set = ptcgio_data.sets.iter().find(|set| &set.name == name);
+            SetProfile::new(
+                name,
+                set.and_then(|set| set.ptcgo_code.clone()),
+                set.map(|set| set.series.clone()),
+                set.map(|set| set.release_date.as_str()),
+                cards,
+            )
+        })
+        .collect_vec();
+    let listings = pkmn_data::DataFetcher::default()
+        .with_http(http.clone())
+        .list_sets()
+        .await?;
+    let pkmn_only = pkmn_sets
+        .iter()
+        .filter(|(name, _)| !ptcgio_sets.contains_key(*name))
+        .map(|(name, cards)| {
+            let listing = listings.iter().find(|listing| &listing.name == name);
+            SetProfile::new(
+                name,
+                listing.and_then(|listing| listing.code.clone()),
+                listing.map(|listing| listing.series.clone()),
+                listing
+                    .and_then(|listing| listing.stored.as_ref())
+                    .and_then(|set| set.released.as_deref()),
+                cards,
+            )
+        })
+        .collect_vec();
+
+    let suggestions = mapping::suggest(&ptcgio_only, &pkmn_only);
+    for suggestion in &suggestions.scores {
+        println!(
+            "{:.3} {} -> {}",
+            suggestion.score, suggestion.ptcgio, suggestion.pkmn
+        );
+    }
+
+    let sets_dir = Path::new("diffs").join("sets");
+    tokio::fs::create_dir_all(&sets_dir).await?;
+    let mut writer = BufWriter::new(File::create(sets_dir.join("suggested_mappings.json")).await?);
+    writer
+        .write_all(&serde_json::to_vec_pretty(&suggestions)?)
+        .await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Prints the set names of both datasets next to each other with their card counts, one row
+/// per set name, followed by how many sets they have in common
+fn print_set_comparison(
+    ptcgio_sets: &BTreeMap<String, Vec<Card>>,
+    pkmn_sets: &BTreeMap<String, Vec<Card>>,
+) {
+    let column = |sets: &BTreeMap<String, Vec<Card>>, name: &String| {
+        sets.get(name)
+            .map(|cards| format!("{} ({})", name, cards.len()))
+            .unwrap_or_default()
+    };
+    let names = ptcgio_sets
+        .keys()
+        .chain(pkmn_sets.keys())
+        .unique()
+        .sorted()
+        .collect_vec();
+    let width = names
+        .iter()
+        .map(|name| column(ptcgio_sets, name).chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("ptcgio".len());
+
+    println!();
+    println!("{:<width$} | pkmn", "ptcgio", width = width);
+    for name in &names {
+        let left = column(ptcgio_sets, name);
+        let padding = width - left.chars().count();
+        println!(
+            "{}{} | {}",
+            left,
+            " ".repeat(padding),
+            column(pkmn_sets, name)
+        );
+    }
+    let overlap = names
+        .iter()
+        .filter(|name| ptcgio_sets.contains_key(**name) && pkmn_sets.contains_key(**name))
+        .count();
+    println!();
+    println!(
+        "{} sets in both, {} only in ptcgio, {} only in pkmn",
+        overlap,
+        ptcgio_sets.len() - overlap,
+        pkmn_sets.len() - overlap
+    );
+}
+
+/// Checks the mapping file before anything is fetched from pkmncards beyond its list of sets,
+/// reporting every problem at once
+async fn validate_mapping(
+    mapping_operations: &DatasetMappings,
+    ptcgio_data: &ptcgio_data::Data,
+    http: &pkmn_data::HttpOptions,
+) -> Result<()> {
+    let ptcgio_sets = ptcgio_data
+        .sets
+        .iter()
+        .map(|set| (set.name.clone(), Some(set.cards.len())))
+        .collect();
+    let pkmn_sets = pkmn_data::DataFetcher::default()
+        .with_http(http.clone())
+        .list_sets()
+        .await?
+        .into_iter()
+        .map(|listing| (listing.name, listing.stored.map(|set| set.cards.len())))
+        .collect();
+
+    let problems = mapping_operations.validate(&ptcgio_sets, &pkmn_sets);
+    for (dataset, problem) in &problems {
+        eprintln!("{}: {}", dataset, problem);
+    }
+    if !problems.is_empty() {
+        bail!("The mapping file has {} problems", problems.len());
+    }
+    Ok(())
+}
+
+/// Applies the mappings to both datasets, leaving the ptcgio sets in `ptcgio_data` without
+/// their cards. A dry run prints what each operation does.
+async fn load_datasets(
+    ptcgio_data: &mut ptcgio_data::Data,
+    mapping_operations: &DatasetMappings,
+    http: &pkmn_data::HttpOptions,
+    dry_run: bool,
+) -> Result<(BTreeMap<String, Vec<Card>>, BTreeMap<String, Vec<Card>>)> {
+    validate_mapping(mapping_operations, ptcgio_data, http).await?;
+
+    if dry_run {
+        println!("ptcgio operations:");
+    }
+    let ptcgio_sets = process_dataset(
         ptcgio_data
             .sets
-            .drain(..)
-            .map(|set| pkmn_data::Set::new(set.name.as_str(), set.cards)),
+            .iter_mut()
+            .map(|set| pkmn_data::Set::new(set.name.as_str(), std::mem::take(&mut set.cards))),
         &mapping_operations.ptcgio,
+        dry_run,
     )
     .await?;
 
-    let mut pkmn_data = pkmn_data::DataFetcher::default().fetch().await?;
-    let pkmn_data = process_dataset(
+    let mut pkmn_data = pkmn_data::DataFetcher::default()
+        .with_http(http.clone())
+        .fetch()
+        .await?;
+    if dry_run {
+        println!("\npkmn operations:");
+    }
+    let pkmn_sets = process_dataset(
         pkmn_data
             .all_series
             .drain(..)
             .flat_map(|series| series.sets),
         &mapping_operations.pkmn,
+        dry_run,
     )
     .await?;
 
+    Ok((ptcgio_sets, pkmn_sets))
+}
+
+async fn run_diff(
+    ptcgio_data: &ptcgio_data::Data,
+    mut ptcgio_sets: BTreeMap<String, Vec<Card>>,
+    mut pkmn_sets: BTreeMap<String, Vec<Card>>,
+    decisions: &Decisions,
+) -> Result<()> {
     let diffs_dir = PathBuf::from_str("diffs")?;
 
-    let pkmn_sets_set = pkmn_data.keys().cloned().collect::<HashSet<String>>();
-    let ptcgio_sets_set = ptcgio_data.keys().cloned().collect::<HashSet<String>>();
+    let pkmn_sets_set = pkmn_sets.keys().cloned().collect::<HashSet<String>>();
+    let ptcgio_sets_set = ptcgio_sets.keys().cloned().collect::<HashSet<String>>();
+
+    reconcile_data(
+        Path::new("merged"),
+        &ptcgio_data.sets,
+        &ptcgio_sets,
+        &pkmn_sets,
+        decisions,
+    )
+    .await?;
 
     let intersect =
         extract_and_write_set_diffs(&diffs_dir, &pkmn_sets_set, &ptcgio_sets_set).await?;
-    let combined_data = combine_data(ptcgio_data, pkmn_data, intersect)?;
+    for set_name in &intersect {
+        if let (Some(ptcgio_cards), Some(pkmn_cards)) =
+            (ptcgio_sets.get_mut(set_name), pkmn_sets.get_mut(set_name))
+        {
+            decisions.resolve(set_name, ptcgio_cards, pkmn_cards, Resolve::Both)?;
+        }
+    }
+    write_discrepancies(&diffs_dir, &ptcgio_sets, &pkmn_sets, &intersect).await?;
+    let combined_data = combine_data(ptcgio_sets, pkmn_sets, intersect)?;
     diff_data(diffs_dir, combined_data).await?;
 
     Ok(())
 }
 
+/// Patches every discrepancy in `fields` plus the ones a reviewer decided in favour of
+/// pkmncards or overrode, skipping those decided in favour of ptcgio or ignored
+async fn run_patch(
+    ptcgio_data: &ptcgio_data::Data,
+    mut ptcgio_sets: BTreeMap<String, Vec<Card>>,
+    mut pkmn_sets: BTreeMap<String, Vec<Card>>,
+    decisions: &Decisions,
+    fields: &[String],
+    commit: bool,
+) -> Result<()> {
+    for (set_name, ptcgio_cards) in ptcgio_sets.iter_mut() {
+        if let Some(pkmn_cards) = pkmn_sets.get_mut(set_name) {
+            decisions.resolve(set_name, ptcgio_cards, pkmn_cards, Resolve::Pkmn)?;
+        }
+    }
+
+    let accepted = ptcgio_sets
+        .iter()
+        .filter_map(|(set_name, ptcgio_cards)| {
+            pkmn_sets
+                .get(set_name)
+                .map(|pkmn_cards| diff::discrepancies(set_name, ptcgio_cards, pkmn_cards))
+        })
+        .flatten_ok()
+        .filter_ok(|discrepancy| patch::accepts(discrepancy, decisions, fields))
+        .collect::<Result<Vec<Discrepancy>>>()?;
+
+    let changed = patch::apply(ptcgio_data, &accepted).await?;
+    println!(
+        "Applied {} discrepancies to {} files",
+        accepted.len(),
+        changed.len()
+    );
+
+    if commit && !changed.is_empty() {
+        let oid = patch::commit(
+            ptcgio_data.path(),
+            &changed,
+            "Correct card data from pkmncards",
+        )?;
+        println!("Committed {}", oid);
+    }
+    Ok(())
+}
+
+async fn reconcile_data(
+    merged_dir: &Path,
+    ptcgio_sets: &[ptcgio_data::Set],
+    ptcgio_data: &BTreeMap<String, Vec<Card>>,
+    pkmn_data: &BTreeMap<String, Vec<Card>>,
+    decisions: &Decisions,
+) -> Result<()> {
+    let policy_path = Path::new("mapping/reconcile_policy.json");
+    let policy = if policy_path.exists() {
+        Policy::load(policy_path).await?
+    } else {
+        Policy::default()
+    };
+
+    let reconciled = reconcile::reconcile(ptcgio_sets, ptcgio_data, pkmn_data, &policy, decisions)?;
+    reconciled.write(merged_dir).await?;
+    println!("Reconciled sets: {}", reconciled.sets.len());
+    Ok(())
+}
+
 async fn extract_and_write_set_diffs(
     diffs_dir: &PathBuf,
     pkmn_sets_set: &HashSet<String>,
@@ -176,7 +976,49 @@ async fn extract_and_write_set_diffs(
     Ok(intersect)
 }
 
+async fn write_discrepancies(
+    diffs_dir: &Path,
+    ptcgio_sets: &BTreeMap<String, Vec<Card>>,
+    pkmn_sets: &BTreeMap<String, Vec<Card>>,
+    intersect: &HashSet<String>,
+) -> Result<()> {
+    for set_name in intersect {
+        let strip = |cards: Option<&Vec<Card>>| {
+            cards
+                .into_iter()
+                .flatten()
+                .cloned()
+                .map(strip_one_sided_fields)
+                .collect_vec()
+        };
+        let discrepancies = diff::discrepancies(
+            set_name,
+            &strip(ptcgio_sets.get(set_name)),
+            &strip(pkmn_sets.get(set_name)),
+        )?;
+
+        let diff_dir = diffs_dir.join(set_name.to_snek_case());
+        tokio::fs::create_dir_all(&diff_dir).await?;
+        let file = File::create(diff_dir.join("discrepancies").with_extension("json")).await?;
+        let mut writer = BufWriter::new(file);
+        writer
+            .write_all(&serde_json::to_vec_pretty(&discrepancies)?)
+            .await?;
+        writer.flush().await?;
+    }
+    Ok(())
+}
+
 type PokemonNameBucket = BTreeMap<String, Vec<Card>>;
+
+/// An entry of a set's `data.json`, the cards of one name in both datasets
+#[derive(Serialize, JsonSchema)]
+struct MergedCards {
+    #[schemars(with = "Option<Vec<Card>>")]
+    ptcgio: Value,
+    #[schemars(with = "Option<Vec<Card>>")]
+    pkmn: Value,
+}
 type CombinedSets = (PokemonNameBucket, PokemonNameBucket);
 
 fn combine_data(
@@ -195,6 +1037,7 @@ fn combine_data(
                     .and_then(|val| {
                         Ok((
                             val.into_iter()
+                                .map(strip_one_sided_fields)
                                 .into_group_map_by(|card| card.name.clone())
                                 .into_iter()
                                 .collect::<PokemonNameBucket>(),
@@ -202,6 +1045,7 @@ fn combine_data(
                                 .remove(key)
                                 .context("Failed to get set from pkmn_data")?
                                 .into_iter()
+                                .map(strip_one_sided_fields)
                                 .into_group_map_by(|card| card.name.clone())
                                 .into_iter()
                                 .collect::<PokemonNameBucket>(),
@@ -241,12 +1085,12 @@ async fn diff_data(diffs_dir: PathBuf, combined_data: HashMap<String, CombinedSe
                     &pkmn_data.get(key.as_str()),
                 )?)?;
 
-                Ok::<HashMap<&str, Value>, anyhow::Error>(HashMap::from([
-                    ("ptcgio", sort_value(ptcgio)),
-                    ("pkmn", sort_value(pkmn)),
-                ]))
+                Ok::<MergedCards, anyhow::Error>(MergedCards {
+                    ptcgio: sort_value(ptcgio),
+                    pkmn: sort_value(pkmn),
+                })
             })
-            .collect::<Result<Vec<HashMap<&str, Value>>>>()?;
+            .collect::<Result<Vec<MergedCards>>>()?;
         let merged_data_str = serde_json::to_string_pretty(&merged_data)?;
 
         let mut writer = BufWriter::new(data_log);
@@ -256,9 +1100,9 @@ async fn diff_data(diffs_dir: PathBuf, combined_data: HashMap<String, CombinedSe
         let mut writer = BufWriter::new(diff_log);
         // let output = serde_json::to_vec_pretty(&p)?;
 
-        for (idx, mut data) in merged_data.into_iter().enumerate() {
-            let pkmn_val = data.remove("pkmn").unwrap();
-            let ptcgio_val = data.remove("ptcgio").unwrap();
+        for (idx, data) in merged_data.into_iter().enumerate() {
+            let pkmn_val = data.pkmn;
+            let ptcgio_val = data.ptcgio;
 
             let diffset = diff::diff(&ptcgio_val, &pkmn_val).collect_vec();
 
@@ -325,6 +1169,7 @@ fn sort_value(val: Value) -> Value {
         Value::Number(v) => Value::Number(v),
         Value::Object(v) => Value::Object(
             v.into_iter()
+                .sorted_by(|(k1, _), (k2, _)| k1.cmp(k2))
                 .map(|(key, value)| (key, sort_value(value)))
                 .collect(),
         ),
@@ -1,14 +1,25 @@
+mod suggest;
+
+pub use suggest::{suggest, MappingSuggestions, SetProfile};
+
 use crate::ptcgio_data::Card;
-use anyhow::{anyhow, bail, Result};
-use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, bail, Context, Result};
+use itertools::Itertools;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
 use serde_with::{serde_as, DisplayFromStr};
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
 use std::iter;
 use std::path::Path;
 use tokio::fs::File;
 use tokio::io::{AsyncReadExt, BufReader};
 
-#[derive(Serialize, Deserialize)]
+/// The mapping file, `mapping/set_operations.json`
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct DatasetMappings {
     pub ptcgio: MappingOperations,
     pub pkmn: MappingOperations,
@@ -22,14 +33,183 @@ impl DatasetMappings {
         reader.read_to_end(&mut buffer).await?;
         Ok(serde_json::from_slice(&buffer)?)
     }
+
+    /// Checks both sides' operations against the sets each dataset has, see
+    /// [`MappingOperations::validate`]
+    pub fn validate(
+        &self,
+        ptcgio_sets: &AvailableSets,
+        pkmn_sets: &AvailableSets,
+    ) -> Vec<(&'static str, MappingProblem)> {
+        self.ptcgio
+            .validate(ptcgio_sets)
+            .into_iter()
+            .map(|problem| ("ptcgio", problem))
+            .chain(
+                self.pkmn
+                    .validate(pkmn_sets)
+                    .into_iter()
+                    .map(|problem| ("pkmn", problem)),
+            )
+            .collect()
+    }
+}
+
+/// The set names a dataset has, with their card count if it is known
+pub type AvailableSets = BTreeMap<String, Option<usize>>;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum MappingProblem {
+    UnknownSource {
+        operation: &'static str,
+        set: String,
+    },
+    NameCollision {
+        operation: &'static str,
+        set: String,
+    },
+    ExtractTargetOutOfRange {
+        set: String,
+        index: usize,
+        count: usize,
+    },
+    ExtractSourceOutOfRange {
+        set: String,
+        from: String,
+        index: usize,
+        count: usize,
+    },
+    ExtractSlotFilledTwice {
+        set: String,
+        index: usize,
+    },
+    UnfilledExtractSlots {
+        set: String,
+        indices: Vec<usize>,
+    },
+    MissingMergeTarget {
+        set: String,
+        target: String,
+    },
+    InvalidSelector {
+        set: String,
+        from: String,
+        reason: String,
+    },
+    PositionsAfterTake {
+        set: String,
+        from: String,
+    },
 }
 
-#[derive(Serialize, Deserialize)]
+impl Display for MappingProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MappingProblem::UnknownSource { operation, set } => {
+                write!(
+                    f,
+                    "{} refers to the set {} which does not exist",
+                    operation, set
+                )
+            }
+            MappingProblem::NameCollision { operation, set } => {
+                write!(
+                    f,
+                    "{} would create the set {} which already exists",
+                    operation, set
+                )
+            }
+            MappingProblem::ExtractTargetOutOfRange { set, index, count } => write!(
+                f,
+                "extracting {} fills index {} but the set only has {} cards",
+                set, index, count
+            ),
+            MappingProblem::ExtractSourceOutOfRange {
+                set,
+                from,
+                index,
+                count,
+            } => write!(
+                f,
+                "extracting {} takes index {} from {} which only has {} cards",
+                set, index, from, count
+            ),
+            MappingProblem::ExtractSlotFilledTwice { set, index } => {
+                write!(f, "extracting {} fills index {} more than once", set, index)
+            }
+            MappingProblem::UnfilledExtractSlots { set, indices } => write!(
+                f,
+                "extracting {} leaves the indices {:?} unfilled",
+                set, indices
+            ),
+            MappingProblem::MissingMergeTarget { set, target } => write!(
+                f,
+                "merge of {} targets {} which does not exist by then",
+                set, target
+            ),
+            MappingProblem::InvalidSelector { set, from, reason } => write!(
+                f,
+                "extracting {} selects cards of {} with an invalid selector: {}",
+                set, from, reason
+            ),
+            MappingProblem::PositionsAfterTake { set, from } => write!(
+                f,
+                "extracting {} takes cards of {} by position after taking selected cards out \
+                 of it",
+                set, from
+            ),
+        }
+    }
+}
+
+/// The operations applied to one dataset's sets, run in the order they are listed.
+///
+/// The mapping file holds either a list of [`Operation`]s or the older object form with one
+/// map per kind of operation, see [`LegacyOperations`].
 pub struct MappingOperations {
-    ignore: HashSet<String>,
-    merge: HashMap<String, String>,
-    map: HashMap<String, String>,
-    extract: HashMap<String, ExtractOp>,
+    operations: Vec<Operation>,
+}
+
+impl Serialize for MappingOperations {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.operations.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MappingOperations {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        // Decide on the form up front so errors point into it instead of saying neither matched
+        let value = Value::deserialize(deserializer)?;
+        let operations = if value.is_array() {
+            serde_json::from_value(value).map_err(de::Error::custom)?
+        } else {
+            serde_json::from_value::<LegacyOperations>(value)
+                .map_err(de::Error::custom)?
+                .into_operations()
+        };
+        Ok(MappingOperations { operations })
+    }
+}
+
+// Only exists for the schema, the forms are told apart by hand when deserializing
+/// A list of operations run in order, or the older object form with one map per kind of
+/// operation
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[serde(untagged)]
+enum MappingOperationsForm {
+    Ordered(Vec<Operation>),
+    Legacy(LegacyOperations),
+}
+
+impl JsonSchema for MappingOperations {
+    fn schema_name() -> String {
+        "MappingOperations".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        MappingOperationsForm::json_schema(gen)
+    }
 }
 
 impl MappingOperations {
@@ -37,79 +217,1286 @@ impl MappingOperations {
         &self,
         mut current_sets: BTreeMap<String, Vec<Card>>,
     ) -> Result<BTreeMap<String, Vec<Card>>> {
-        // Extract step
-        for (extracted_name, operation) in &self.extract {
-            if current_sets.contains_key(extracted_name) {
-                bail!("This dataset already has the set {}", extracted_name);
-            }
-            let mut extracted_vec: Vec<Option<Card>> = Vec::with_capacity(operation.count);
-            extracted_vec.extend(iter::repeat(None).take(operation.count));
-            for card_group in &operation.cards {
-                let origin_set = current_sets.get(&card_group.from).ok_or(anyhow!(
-                    "Attempted to extract a pokemon from a set not contained in this dataset: {}",
-                    card_group.from
-                ))?;
+        for operation in &self.operations {
+            operation.apply(&mut current_sets)?;
+        }
+        Ok(current_sets)
+    }
 
-                for (target, source) in &card_group.numbers {
-                    extracted_vec[*target] = Some(origin_set[*source].clone());
-                }
+    /// Same as [`Self::map`] but prints every operation with what it did to the sets
+    pub async fn dry_run(
+        &self,
+        mut current_sets: BTreeMap<String, Vec<Card>>,
+    ) -> Result<BTreeMap<String, Vec<Card>>> {
+        for (idx, operation) in self.operations.iter().enumerate() {
+            println!("{}. {}", idx + 1, operation);
+            let effects = operation
+                .apply_and_describe(&mut current_sets)
+                .with_context(|| format!("Operation {} failed", idx + 1))?;
+            if effects.is_empty() {
+                println!("   no effect");
+            }
+            for effect in effects {
+                println!("   {}", effect);
             }
+        }
+        Ok(current_sets)
+    }
 
-            current_sets.insert(
-                extracted_name.to_string(),
-                extracted_vec
+    /// Runs the operations against set names only, in the same order as [`Self::map`], and
+    /// reports every problem instead of stopping at the first one. Card indices can only be
+    /// checked for sets whose card count is known.
+    pub fn validate(&self, available: &AvailableSets) -> Vec<MappingProblem> {
+        let mut problems = Vec::new();
+        let mut sets = available.clone();
+        for operation in &self.operations {
+            operation.validate(&mut sets, &mut problems);
+        }
+        problems
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "op", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Operation {
+    /// Builds a new set out of cards of other sets, see [`ExtractGroup`]
+    Extract {
+        name: String,
+        /// The number of slots filled by position, selected cards come after them
+        #[serde(default)]
+        count: usize,
+        cards: Vec<ExtractGroup>,
+    },
+    /// Moves cards between sets by number
+    Move {
+        from: String,
+        to: String,
+        numbers: Vec<String>,
+    },
+    /// Drops cards from a set by number
+    Drop { set: String, numbers: Vec<String> },
+    /// Changes the number of cards in a set, old number to new number
+    Renumber {
+        set: String,
+        numbers: BTreeMap<String, String>,
+    },
+    /// Changes the name of cards in a set, number to new name
+    Rename {
+        set: String,
+        names: BTreeMap<String, String>,
+    },
+    /// Renames a set
+    Map { from: String, to: String },
+    /// Adds the cards of a set to another set
+    Merge { from: String, into: String },
+    /// Drops a whole set
+    Ignore { set: String },
+}
+
+impl Operation {
+    fn apply(&self, current_sets: &mut BTreeMap<String, Vec<Card>>) -> Result<()> {
+        match self {
+            Operation::Extract { name, count, cards } => {
+                if current_sets.contains_key(name) {
+                    bail!("This dataset already has the set {}", name);
+                }
+                let mut extracted_vec: Vec<Option<Card>> = Vec::with_capacity(*count);
+                extracted_vec.extend(iter::repeat(None).take(*count));
+                let mut selected = Vec::new();
+                let mut taken_from = BTreeSet::new();
+                for card_group in cards {
+                    let origin_set = current_sets.get_mut(card_group.from()).ok_or(anyhow!(
+                        "Attempted to extract a pokemon from a set not contained in this dataset: {}",
+                        card_group.from()
+                    ))?;
+
+                    match card_group {
+                        ExtractGroup::Positions { from, numbers } => {
+                            if taken_from.contains(from) {
+                                bail!(
+                                    "Extracting {} takes cards of {} by position after taking \
+                                     selected cards out of it, list the positions first",
+                                    name,
+                                    from
+                                );
+                            }
+                            for (target, source) in numbers {
+                                let card = origin_set.get(*source).ok_or(anyhow!(
+                                    "Extracting {} takes index {} from {} which only has {} cards",
+                                    name,
+                                    source,
+                                    from,
+                                    origin_set.len()
+                                ))?;
+                                *extracted_vec.get_mut(*target).ok_or(anyhow!(
+                                    "Extracting {} fills index {} but the set only has {} cards",
+                                    name,
+                                    target,
+                                    count
+                                ))? = Some(card.clone());
+                            }
+                        }
+                        ExtractGroup::Select { from, select, take } => {
+                            let positions = origin_set
+                                .iter()
+                                .positions(|card| select.matches(card))
+                                .collect_vec();
+                            if positions.is_empty() {
+                                bail!("Extracting {} selected no cards of {}", name, from);
+                            }
+                            if *take {
+                                taken_from.insert(from);
+                                // Remove from the back so the remaining positions stay valid
+                                let start = selected.len();
+                                for idx in positions.into_iter().rev() {
+                                    selected.push(origin_set.remove(idx));
+                                }
+                                selected[start..].reverse();
+                            } else {
+                                selected.extend(
+                                    positions.into_iter().map(|idx| origin_set[idx].clone()),
+                                );
+                            }
+                        }
+                    }
+                }
+
+                let mut extracted = extracted_vec
                     .into_iter()
                     .enumerate()
                     .map(|(idx, opt)| opt.ok_or(anyhow!("Card at index {} was not filled", idx)))
-                    .collect::<Result<Vec<Card>>>()?,
-            );
+                    .collect::<Result<Vec<Card>>>()?;
+                extracted.extend(selected);
+                current_sets.insert(name.to_string(), extracted);
+            }
+            Operation::Move { from, to, numbers } => {
+                for number in numbers {
+                    let origin_set = current_sets.get_mut(from).ok_or(anyhow!(
+                        "Attempted to move card #{} out of {} but the set does not exist",
+                        number,
+                        from
+                    ))?;
+                    let card = origin_set.remove(
+                        card_position(origin_set, from, number)
+                            .with_context(|| format!("Failed to move a card to {}", to))?,
+                    );
+                    current_sets
+                        .get_mut(to)
+                        .ok_or(anyhow!(
+                            "Attempted to move card #{} into {} but the set does not exist",
+                            number,
+                            to
+                        ))?
+                        .push(card);
+                }
+            }
+            Operation::Drop {
+                set: set_name,
+                numbers,
+            } => {
+                let set = current_sets.get_mut(set_name).ok_or(anyhow!(
+                    "Attempted to drop cards from {} but the set does not exist",
+                    set_name
+                ))?;
+                for number in numbers {
+                    set.remove(
+                        card_position(set, set_name, number).context("Failed to drop a card")?,
+                    );
+                }
+            }
+            Operation::Renumber {
+                set: set_name,
+                numbers,
+            } => {
+                let set = current_sets.get_mut(set_name).ok_or(anyhow!(
+                    "Attempted to renumber cards in {} but the set does not exist",
+                    set_name
+                ))?;
+                // Resolve every card before changing any number so swaps don't collide
+                let positions = numbers
+                    .iter()
+                    .map(|(old, new)| Ok((card_position(set, set_name, old)?, new)))
+                    .collect::<Result<Vec<_>>>()
+                    .context("Failed to renumber a card")?;
+                for (idx, new) in positions {
+                    set[idx].number = new.to_string();
+                }
+            }
+            Operation::Rename {
+                set: set_name,
+                names,
+            } => {
+                let set = current_sets.get_mut(set_name).ok_or(anyhow!(
+                    "Attempted to rename cards in {} but the set does not exist",
+                    set_name
+                ))?;
+                for (number, name) in names {
+                    let idx =
+                        card_position(set, set_name, number).context("Failed to rename a card")?;
+                    set[idx].name = name.to_string();
+                }
+            }
+            Operation::Map { from, to } => {
+                let moving = current_sets.remove(from).ok_or(anyhow!(
+                    "Attempted to map {} to {} but {} did not exist in the dataset",
+                    from,
+                    to,
+                    from
+                ))?;
+                if current_sets.insert(to.to_string(), moving).is_some() {
+                    bail!("Mapping {} to {} replaced an existing set", from, to);
+                }
+            }
+            Operation::Merge { from, into } => {
+                let moving = current_sets
+                    .remove(from)
+                    .ok_or(anyhow!("The source set {} for merging did not exist", from))?;
+                current_sets
+                    .get_mut(into)
+                    .ok_or(anyhow!("The target set {} did not exist", into))?
+                    .extend(moving);
+            }
+            Operation::Ignore { set } => {
+                current_sets.remove(set).ok_or(anyhow!(
+                    "The set {} you wanted to ignore does not exist",
+                    set
+                ))?;
+            }
         }
+        Ok(())
+    }
 
-        // Map step
-        for (source_name, target_name) in &self.map {
-            let moving = current_sets.remove(source_name).ok_or(anyhow!(
-                "Attempted to map {} to {} but {} did not exist in the dataset",
-                source_name,
-                target_name,
-                target_name
-            ))?;
-            current_sets.insert(target_name.to_string(), moving);
+    /// The names of the sets the operation reads or changes
+    fn set_names(&self) -> BTreeSet<&str> {
+        match self {
+            Operation::Extract { name, cards, .. } => iter::once(name.as_str())
+                .chain(cards.iter().map(ExtractGroup::from))
+                .collect(),
+            Operation::Move { from, to, .. } | Operation::Map { from, to } => {
+                BTreeSet::from([from.as_str(), to.as_str()])
+            }
+            Operation::Merge { from, into } => BTreeSet::from([from.as_str(), into.as_str()]),
+            Operation::Drop { set, .. }
+            | Operation::Renumber { set, .. }
+            | Operation::Rename { set, .. }
+            | Operation::Ignore { set } => BTreeSet::from([set.as_str()]),
         }
+    }
+
+    /// Applies the operation and describes what it did to the sets it names, see
+    /// [`describe_effects`]
+    fn apply_and_describe(
+        &self,
+        current_sets: &mut BTreeMap<String, Vec<Card>>,
+    ) -> Result<Vec<String>> {
+        let names = self.set_names();
+        let named = |sets: &BTreeMap<String, Vec<Card>>| {
+            names
+                .iter()
+                .filter_map(|name| sets.get_key_value(*name))
+                .map(|(name, cards)| (name.clone(), cards.clone()))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let before = named(current_sets);
+        self.apply(current_sets)?;
+        Ok(describe_effects(&before, &named(current_sets)))
+    }
+
+    fn validate(&self, sets: &mut AvailableSets, problems: &mut Vec<MappingProblem>) {
+        let mut require = |operation: &'static str, set: &String, sets: &AvailableSets| {
+            if !sets.contains_key(set) {
+                problems.push(MappingProblem::UnknownSource {
+                    operation,
+                    set: set.to_string(),
+                });
+            }
+        };
+        match self {
+            Operation::Extract { name, count, cards } => {
+                if sets.contains_key(name) {
+                    problems.push(MappingProblem::NameCollision {
+                        operation: "extract",
+                        set: name.to_string(),
+                    });
+                }
+
+                let mut filled = BTreeSet::new();
+                let mut taken_from = BTreeSet::new();
+                // Selections have a card count only known once the cards are there
+                let mut selects = false;
+                for card_group in cards {
+                    let source_count = match sets.get(card_group.from()) {
+                        Some(count) => *count,
+                        None => {
+                            problems.push(MappingProblem::UnknownSource {
+                                operation: "extract",
+                                set: card_group.from().to_string(),
+                            });
+                            None
+                        }
+                    };
+
+                    let numbers = match card_group {
+                        ExtractGroup::Positions { from, numbers } => {
+                            if taken_from.contains(from) {
+                                problems.push(MappingProblem::PositionsAfterTake {
+                                    set: name.to_string(),
+                                    from: from.to_string(),
+                                });
+                            }
+                            numbers
+                        }
+                        ExtractGroup::Select { from, select, take } => {
+                            if let Err(reason) = select.check() {
+                                problems.push(MappingProblem::InvalidSelector {
+                                    set: name.to_string(),
+                                    from: from.to_string(),
+                                    reason,
+                                });
+                            }
+                            if *take {
+                                taken_from.insert(from);
+                                if let Some(count) = sets.get_mut(from) {
+                                    *count = None;
+                                }
+                            }
+                            selects = true;
+                            continue;
+                        }
+                    };
+                    for (target, source) in numbers {
+                        if target >= count {
+                            problems.push(MappingProblem::ExtractTargetOutOfRange {
+                                set: name.to_string(),
+                                index: *target,
+                                count: *count,
+                            });
+                        } else if !filled.insert(*target) {
+                            problems.push(MappingProblem::ExtractSlotFilledTwice {
+                                set: name.to_string(),
+                                index: *target,
+                            });
+                        }
+                        if let Some(source_count) =
+                            source_count.filter(|source_count| source >= source_count)
+                        {
+                            problems.push(MappingProblem::ExtractSourceOutOfRange {
+                                set: name.to_string(),
+                                from: card_group.from().to_string(),
+                                index: *source,
+                                count: source_count,
+                            });
+                        }
+                    }
+                }
 
-        // Merge Step
-        for (source_name, target_name) in &self.merge {
-            let moving = current_sets.remove(source_name).ok_or(anyhow!(
-                "The source set {} for merging did not exist",
-                source_name
-            ))?;
-            current_sets
-                .get_mut(target_name)
-                .ok_or(anyhow!("The target set {} did not exist", target_name))?
-                .extend(moving);
+                let unfilled = (0..*count)
+                    .filter(|idx| !filled.contains(idx))
+                    .collect_vec();
+                if !unfilled.is_empty() {
+                    problems.push(MappingProblem::UnfilledExtractSlots {
+                        set: name.to_string(),
+                        indices: unfilled,
+                    });
+                }
+                sets.insert(name.to_string(), Some(*count).filter(|_| !selects));
+            }
+            Operation::Move { from, to, numbers } => {
+                require("move", from, sets);
+                require("move", to, sets);
+                if let Some(count) = sets.get_mut(from).and_then(|count| count.as_mut()) {
+                    *count = count.saturating_sub(numbers.len());
+                }
+                if let Some(count) = sets.get_mut(to).and_then(|count| count.as_mut()) {
+                    *count += numbers.len();
+                }
+            }
+            Operation::Drop { set, numbers } => {
+                require("drop", set, sets);
+                if let Some(count) = sets.get_mut(set).and_then(|count| count.as_mut()) {
+                    *count = count.saturating_sub(numbers.len());
+                }
+            }
+            Operation::Renumber { set, .. } => require("renumber", set, sets),
+            Operation::Rename { set, .. } => require("rename", set, sets),
+            Operation::Map { from, to } => match sets.remove(from) {
+                Some(count) => {
+                    if sets.insert(to.to_string(), count).is_some() {
+                        problems.push(MappingProblem::NameCollision {
+                            operation: "map",
+                            set: to.to_string(),
+                        });
+                    }
+                }
+                None => problems.push(MappingProblem::UnknownSource {
+                    operation: "map",
+                    set: from.to_string(),
+                }),
+            },
+            Operation::Merge { from, into } => {
+                let source = sets.remove(from);
+                if source.is_none() {
+                    problems.push(MappingProblem::UnknownSource {
+                        operation: "merge",
+                        set: from.to_string(),
+                    });
+                }
+                match sets.get_mut(into) {
+                    Some(count) => {
+                        *count = count
+                            .zip(source.flatten())
+                            .map(|(count, source)| count + source)
+                    }
+                    None => problems.push(MappingProblem::MissingMergeTarget {
+                        set: from.to_string(),
+                        target: into.to_string(),
+                    }),
+                }
+            }
+            Operation::Ignore { set } => {
+                if sets.remove(set).is_none() {
+                    problems.push(MappingProblem::UnknownSource {
+                        operation: "ignore",
+                        set: set.to_string(),
+                    });
+                }
+            }
         }
+    }
+}
 
-        // Ignore Step
-        for set_name in &self.ignore {
-            current_sets.remove(set_name).ok_or(anyhow!(
-                "The set {} you wanted to ignore does not exist",
-                set_name
-            ))?;
+impl Display for Operation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operation::Extract { name, cards, .. } => write!(
+                f,
+                "extract {} from {}",
+                name,
+                cards.iter().map(ExtractGroup::from).unique().join(", ")
+            ),
+            Operation::Move { from, to, numbers } => {
+                write!(f, "move #{} from {} to {}", numbers.join(", #"), from, to)
+            }
+            Operation::Drop { set, numbers } => {
+                write!(f, "drop #{} from {}", numbers.join(", #"), set)
+            }
+            Operation::Renumber { set, numbers } => {
+                write!(f, "renumber {} cards in {}", numbers.len(), set)
+            }
+            Operation::Rename { set, names } => {
+                write!(f, "rename {} cards in {}", names.len(), set)
+            }
+            Operation::Map { from, to } => write!(f, "map {} to {}", from, to),
+            Operation::Merge { from, into } => write!(f, "merge {} into {}", from, into),
+            Operation::Ignore { set } => write!(f, "ignore {}", set),
         }
+    }
+}
 
-        Ok(current_sets)
+/// Lists how the sets changed between `before` and `after`: sets renamed, added or removed,
+/// card counts changed and cards renumbered or renamed
+fn describe_effects(
+    before: &BTreeMap<String, Vec<Card>>,
+    after: &BTreeMap<String, Vec<Card>>,
+) -> Vec<String> {
+    let card_keys = |cards: &[Card]| {
+        cards
+            .iter()
+            .map(|card| (card.number.clone(), card.name.clone()))
+            .collect_vec()
+    };
+    let mut removed = before
+        .iter()
+        .filter(|(name, _)| !after.contains_key(*name))
+        .collect_vec();
+    let mut effects = Vec::new();
+    for (name, cards) in after {
+        match before.get(name) {
+            Some(old_cards) if old_cards.len() != cards.len() => effects.push(format!(
+                "{}: {} -> {} cards",
+                name,
+                old_cards.len(),
+                cards.len()
+            )),
+            Some(old_cards) => {
+                let changed = card_keys(old_cards)
+                    .into_iter()
+                    .zip(card_keys(cards))
+                    .filter(|(old, new)| old != new)
+                    .count();
+                if changed > 0 {
+                    effects.push(format!("{}: {} cards renumbered or renamed", name, changed));
+                }
+            }
+            None => {
+                // A set that reappears with the same cards under a new name was renamed
+                let keys = card_keys(cards);
+                match removed
+                    .iter()
+                    .position(|(_, old_cards)| card_keys(old_cards) == keys)
+                {
+                    Some(idx) => {
+                        let (old_name, _) = removed.remove(idx);
+                        effects.push(format!(
+                            "renamed {} to {} ({} cards)",
+                            old_name,
+                            name,
+                            cards.len()
+                        ));
+                    }
+                    None => effects.push(format!("added {} ({} cards)", name, cards.len())),
+                }
+            }
+        }
+    }
+    for (name, cards) in removed {
+        effects.push(format!("removed {} ({} cards)", name, cards.len()));
+    }
+    effects
+}
+
+/// The original form of the mapping file, with one map per kind of operation. Its operations
+/// run kind by kind: extract, move, drop, renumber, rename, map, merge and finally ignore, each
+/// kind in the order of its set names.
+#[derive(Deserialize, JsonSchema)]
+struct LegacyOperations {
+    ignore: BTreeSet<String>,
+    merge: BTreeMap<String, String>,
+    map: BTreeMap<String, String>,
+    extract: BTreeMap<String, ExtractOp>,
+    /// Cards to move between sets, by number
+    #[serde(default, rename = "move")]
+    move_cards: Vec<MoveOp>,
+    /// Set name to the numbers of the cards to drop from it
+    #[serde(default)]
+    drop: BTreeMap<String, Vec<String>>,
+    /// Set name to a map of old card number to new card number
+    #[serde(default)]
+    renumber: BTreeMap<String, BTreeMap<String, String>>,
+    /// Set name to a map of card number to new card name
+    #[serde(default)]
+    rename: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl LegacyOperations {
+    fn into_operations(self) -> Vec<Operation> {
+        let extract = self
+            .extract
+            .into_iter()
+            .map(|(name, op)| Operation::Extract {
+                name,
+                count: op.count,
+                cards: op.cards,
+            });
+        let move_cards = self.move_cards.into_iter().map(|op| Operation::Move {
+            from: op.from,
+            to: op.to,
+            numbers: op.numbers,
+        });
+        let drop = self
+            .drop
+            .into_iter()
+            .map(|(set, numbers)| Operation::Drop { set, numbers });
+        let renumber = self
+            .renumber
+            .into_iter()
+            .map(|(set, numbers)| Operation::Renumber { set, numbers });
+        let rename = self
+            .rename
+            .into_iter()
+            .map(|(set, names)| Operation::Rename { set, names });
+        let map = self
+            .map
+            .into_iter()
+            .map(|(from, to)| Operation::Map { from, to });
+        let merge = self
+            .merge
+            .into_iter()
+            .map(|(from, into)| Operation::Merge { from, into });
+        let ignore = self.ignore.into_iter().map(|set| Operation::Ignore { set });
+
+        extract
+            .chain(move_cards)
+            .chain(drop)
+            .chain(renumber)
+            .chain(rename)
+            .chain(map)
+            .chain(merge)
+            .chain(ignore)
+            .collect()
+    }
+}
+
+/// Finds the card with the given number, which has to be unique within the set
+fn card_position(cards: &[Card], set_name: &str, number: &str) -> Result<usize> {
+    let mut positions = cards.iter().positions(|card| card.number == number);
+    match (positions.next(), positions.next()) {
+        (Some(idx), None) => Ok(idx),
+        (None, _) => bail!("There is no card #{} in {}", number, set_name),
+        (Some(_), Some(_)) => bail!("There is more than one card #{} in {}", number, set_name),
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Deserialize, JsonSchema)]
+struct MoveOp {
+    from: String,
+    to: String,
+    numbers: Vec<String>,
+}
+
+#[derive(Deserialize, JsonSchema)]
 struct ExtractOp {
     count: usize,
     cards: Vec<ExtractGroup>,
 }
 
+/// Cards an extract takes from one set, either by their position in it or by a selector
 #[serde_as]
-#[derive(Serialize, Deserialize)]
-struct ExtractGroup {
-    from: String,
-    #[serde_as(as = "HashMap<DisplayFromStr, DisplayFromStr>")]
-    numbers: HashMap<usize, usize>,
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum ExtractGroup {
+    /// Fills slots of the new set, slot index to the index of the card in `from`
+    Positions {
+        from: String,
+        #[serde_as(as = "BTreeMap<DisplayFromStr, DisplayFromStr>")]
+        #[schemars(with = "BTreeMap<String, String>")]
+        numbers: BTreeMap<usize, usize>,
+    },
+    /// Adds the cards of `from` matching `select` after the slots, in the order of `from`.
+    /// With `take` the cards are also removed from `from`, which splits a subset like the
+    /// Trainer Gallery out of its main set. Positions of `from` would shift, so the same extract
+    /// can't take cards of it by position after that.
+    Select {
+        from: String,
+        select: CardSelector,
+        #[serde(default)]
+        take: bool,
+    },
+}
+
+impl ExtractGroup {
+    fn from(&self) -> &str {
+        match self {
+            ExtractGroup::Positions { from, .. } | ExtractGroup::Select { from, .. } => from,
+        }
+    }
+}
+
+/// Selects cards by number and card fields. Every given criterion has to match.
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct CardSelector {
+    /// Exact card numbers
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    numbers: Vec<String>,
+    /// An inclusive range of numbers sharing a prefix, like `["TG01", "TG30"]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    range: Option<(String, String)>,
+    /// A prefix of the number, like `GG` for the Galarian Gallery
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rarity: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    supertype: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    subtype: Option<String>,
+}
+
+impl CardSelector {
+    fn matches(&self, card: &Card) -> bool {
+        if !self.numbers.is_empty() && !self.numbers.contains(&card.number) {
+            return false;
+        }
+        if let Some((low, high)) = &self.range {
+            let in_range = match (
+                split_number(low),
+                split_number(high),
+                split_number(&card.number),
+            ) {
+                (Some((prefix, low)), Some((_, high)), Some((card_prefix, number))) => {
+                    prefix == card_prefix && (low..=high).contains(&number)
+                }
+                _ => false,
+            };
+            if !in_range {
+                return false;
+            }
+        }
+        if let Some(prefix) = &self.prefix {
+            if !card.number.starts_with(prefix.as_str()) {
+                return false;
+            }
+        }
+        if let Some(rarity) = &self.rarity {
+            if card.rarity.as_ref() != Some(rarity) {
+                return false;
+            }
+        }
+        if let Some(supertype) = &self.supertype {
+            if &card.supertype != supertype {
+                return false;
+            }
+        }
+        if let Some(subtype) = &self.subtype {
+            if !card
+                .subtypes
+                .as_ref()
+                .is_some_and(|subtypes| subtypes.contains(subtype))
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Catches selectors that can't be what was meant: ones selecting every card and ranges
+    /// whose ends don't share a prefix
+    fn check(&self) -> std::result::Result<(), String> {
+        if self.numbers.is_empty()
+            && self.range.is_none()
+            && self.prefix.is_none()
+            && self.rarity.is_none()
+            && self.supertype.is_none()
+            && self.subtype.is_none()
+        {
+            return Err("it selects every card, use merge or map instead".to_string());
+        }
+        if let Some((low, high)) = &self.range {
+            match (split_number(low), split_number(high)) {
+                (Some((low_prefix, low_number)), Some((high_prefix, high_number))) => {
+                    if low_prefix != high_prefix {
+                        return Err(format!("the range {}-{} mixes prefixes", low, high));
+                    }
+                    if low_number > high_number {
+                        return Err(format!("the range {}-{} is empty", low, high));
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "the range {}-{} needs numbers ending in digits",
+                        low, high
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Splits a card number like `TG07` into its prefix and numeric part
+fn split_number(number: &str) -> Option<(&str, u32)> {
+    let digits = number.find(|c: char| c.is_ascii_digit())?;
+    let (prefix, digits) = number.split_at(digits);
+    Some((prefix, digits.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn operations(operations: Value) -> MappingOperations {
+        serde_json::from_value(operations).unwrap()
+    }
+
+    /// Sets of cards given by number and name
+    fn sets(sets: &[(&str, &[(&str, &str)])]) -> BTreeMap<String, Vec<Card>> {
+        sets.iter()
+            .map(|(name, cards)| {
+                let cards = cards
+                    .iter()
+                    .map(|(number, card)| Card::stub(&format!("set-{}", number), card))
+                    .collect();
+                (name.to_string(), cards)
+            })
+            .collect()
+    }
+
+    /// The number and name of every card of a set
+    fn cards<'a>(sets: &'a BTreeMap<String, Vec<Card>>, set: &str) -> Vec<(&'a str, &'a str)> {
+        sets[set]
+            .iter()
+            .map(|card| (card.number.as_str(), card.name.as_str()))
+            .collect()
+    }
+
+    fn apply(operation: Value, sets: &mut BTreeMap<String, Vec<Card>>) -> Result<()> {
+        serde_json::from_value::<Operation>(operation)
+            .unwrap()
+            .apply(sets)
+    }
+
+    fn available(sets: &[(&str, Option<usize>)]) -> AvailableSets {
+        sets.iter()
+            .map(|(name, count)| (name.to_string(), *count))
+            .collect()
+    }
+
+    #[test]
+    fn report_unknown_sets_and_collisions() {
+        let sets = available(&[("Base", Some(102)), ("Jungle", Some(64)), ("Fossil", None)]);
+        let problems = operations(json!([
+            { "op": "map", "from": "Base Set", "to": "Base" },
+            { "op": "map", "from": "Jungle", "to": "Fossil" },
+            { "op": "merge", "from": "Fossil", "into": "Team Rocket" },
+            { "op": "ignore", "set": "Gym Heroes" },
+        ]))
+        .validate(&sets);
+        assert_eq!(
+            problems,
+            vec![
+                MappingProblem::UnknownSource {
+                    operation: "map",
+                    set: "Base Set".to_string()
+                },
+                MappingProblem::NameCollision {
+                    operation: "map",
+                    set: "Fossil".to_string()
+                },
+                MappingProblem::MissingMergeTarget {
+                    set: "Fossil".to_string(),
+                    target: "Team Rocket".to_string()
+                },
+                MappingProblem::UnknownSource {
+                    operation: "ignore",
+                    set: "Gym Heroes".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn report_extract_indices_out_of_range() {
+        let sets = available(&[("Base", Some(3)), ("Promos", None), ("Gallery", Some(1))]);
+        let problems = operations(json!([{
+            "op": "extract",
+            "name": "Gallery",
+            "count": 3,
+            "cards": [
+                { "from": "Base", "numbers": { "0": "2", "1": "5", "3": "0" } },
+                { "from": "Promos", "numbers": { "1": "40" } },
+            ],
+        }]))
+        .validate(&sets);
+        assert_eq!(
+            problems,
+            vec![
+                MappingProblem::NameCollision {
+                    operation: "extract",
+                    set: "Gallery".to_string()
+                },
+                MappingProblem::ExtractSourceOutOfRange {
+                    set: "Gallery".to_string(),
+                    from: "Base".to_string(),
+                    index: 5,
+                    count: 3
+                },
+                MappingProblem::ExtractTargetOutOfRange {
+                    set: "Gallery".to_string(),
+                    index: 3,
+                    count: 3
+                },
+                // Promos has no known card count, so only its slot is checked
+                MappingProblem::ExtractSlotFilledTwice {
+                    set: "Gallery".to_string(),
+                    index: 1
+                },
+                MappingProblem::UnfilledExtractSlots {
+                    set: "Gallery".to_string(),
+                    indices: vec![2]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_both_sides() {
+        let mappings = serde_json::from_value::<DatasetMappings>(json!({
+            "ptcgio": [{ "op": "map", "from": "Base", "to": "Base Set" }],
+            "pkmn": [{ "op": "ignore", "set": "Base" }],
+        }))
+        .unwrap();
+        let problems = mappings.validate(
+            &available(&[("Base", Some(102))]),
+            &available(&[("Base Set", Some(102))]),
+        );
+        assert_eq!(
+            problems,
+            vec![(
+                "pkmn",
+                MappingProblem::UnknownSource {
+                    operation: "ignore",
+                    set: "Base".to_string()
+                }
+            )]
+        );
+    }
+
+    fn base_and_promos() -> BTreeMap<String, Vec<Card>> {
+        sets(&[
+            (
+                "Base",
+                &[("1", "Alakazam"), ("2", "Blastoise"), ("SV001", "Mew")],
+            ),
+            ("Promos", &[("1", "Pikachu")]),
+        ])
+    }
+
+    #[test]
+    fn move_and_drop_cards() {
+        let mut sets = base_and_promos();
+        apply(
+            json!({ "op": "move", "from": "Base", "to": "Promos", "numbers": ["SV001"] }),
+            &mut sets,
+        )
+        .unwrap();
+        apply(
+            json!({ "op": "drop", "set": "Base", "numbers": ["1"] }),
+            &mut sets,
+        )
+        .unwrap();
+        assert_eq!(cards(&sets, "Base"), vec![("2", "Blastoise")]);
+        assert_eq!(
+            cards(&sets, "Promos"),
+            vec![("1", "Pikachu"), ("SV001", "Mew")]
+        );
+    }
+
+    #[test]
+    fn renumber_and_rename_cards() {
+        let mut sets = base_and_promos();
+        apply(
+            json!({ "op": "renumber", "set": "Base", "numbers": { "1": "2", "2": "1", "SV001": "SV1" } }),
+            &mut sets,
+        )
+        .unwrap();
+        apply(
+            json!({ "op": "rename", "set": "Base", "names": { "SV1": "Mew ★" } }),
+            &mut sets,
+        )
+        .unwrap();
+        assert_eq!(
+            cards(&sets, "Base"),
+            vec![("2", "Alakazam"), ("1", "Blastoise"), ("SV1", "Mew ★")]
+        );
+    }
+
+    #[test]
+    fn map_sets_without_replacing_any() {
+        let mut sets = base_and_promos();
+        apply(
+            json!({ "op": "map", "from": "Promos", "to": "Wizards Promos" }),
+            &mut sets,
+        )
+        .unwrap();
+        assert_eq!(sets.keys().collect_vec(), vec!["Base", "Wizards Promos"]);
+        let error = apply(
+            json!({ "op": "map", "from": "Base", "to": "Wizards Promos" }),
+            &mut sets,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Mapping Base to Wizards Promos replaced an existing set"
+        );
+    }
+
+    #[test]
+    fn fail_on_missing_or_ambiguous_cards() {
+        let mut sets = base_and_promos();
+        let error = apply(
+            json!({ "op": "move", "from": "Base", "to": "Promos", "numbers": ["3"] }),
+            &mut sets,
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "Failed to move a card to Promos");
+        assert_eq!(
+            error.root_cause().to_string(),
+            "There is no card #3 in Base"
+        );
+
+        let error = apply(
+            json!({ "op": "drop", "set": "Jungle", "numbers": ["1"] }),
+            &mut sets,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Attempted to drop cards from Jungle but the set does not exist"
+        );
+
+        apply(
+            json!({ "op": "renumber", "set": "Base", "numbers": { "2": "1" } }),
+            &mut sets,
+        )
+        .unwrap();
+        let error = apply(
+            json!({ "op": "rename", "set": "Base", "names": { "1": "Dark Alakazam" } }),
+            &mut sets,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.root_cause().to_string(),
+            "There is more than one card #1 in Base"
+        );
+        let error = apply(
+            json!({ "op": "renumber", "set": "Base", "numbers": { "7": "8" } }),
+            &mut sets,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.root_cause().to_string(),
+            "There is no card #7 in Base"
+        );
+    }
+
+    fn listed(operations: &MappingOperations) -> Vec<String> {
+        operations
+            .operations
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn read_operations_in_the_order_listed() {
+        let ordered = operations(json!([
+            { "op": "merge", "from": "B", "into": "C" },
+            { "op": "map", "from": "A", "to": "B" },
+            { "op": "ignore", "set": "D" },
+        ]));
+        assert_eq!(
+            listed(&ordered),
+            vec!["merge B into C", "map A to B", "ignore D"]
+        );
+    }
+
+    #[test]
+    fn read_the_legacy_form_kind_by_kind() {
+        let legacy = operations(json!({
+            "ignore": ["Z", "Y"],
+            "merge": { "M2": "T", "M1": "T" },
+            "map": { "B": "A" },
+            "extract": { "Gallery": { "count": 1, "cards": [{ "from": "Base", "numbers": { "0": "0" } }] } },
+            "move": [{ "from": "Base", "to": "Promos", "numbers": ["9"] }],
+            "drop": { "Base": ["10"] },
+            "renumber": { "Base": { "SV001": "SV1" } },
+            "rename": { "Base": { "1": "Alakazam" } },
+        }));
+        assert_eq!(
+            listed(&legacy),
+            vec![
+                "extract Gallery from Base",
+                "move #9 from Base to Promos",
+                "drop #10 from Base",
+                "renumber 1 cards in Base",
+                "rename 1 cards in Base",
+                "map B to A",
+                "merge M1 into T",
+                "merge M2 into T",
+                "ignore Y",
+                "ignore Z",
+            ]
+        );
+
+        // The card operations came later and may be left out
+        let minimal =
+            operations(json!({ "ignore": [], "merge": {}, "map": { "B": "A" }, "extract": {} }));
+        assert_eq!(listed(&minimal), vec!["map B to A"]);
+    }
+
+    #[test]
+    fn point_errors_into_the_form_used() {
+        let error = serde_json::from_value::<MappingOperations>(json!([{ "op": "rename-set" }]))
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("unknown variant `rename-set`"));
+        let error = serde_json::from_value::<MappingOperations>(json!({ "map": {} }))
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("missing field `ignore`"));
+    }
+
+    fn selector(selector: Value) -> CardSelector {
+        serde_json::from_value(selector).unwrap()
+    }
+
+    fn numbered(number: &str) -> Card {
+        Card::stub(&format!("swsh12-{}", number), "Pikachu")
+    }
+
+    #[test]
+    fn select_cards_by_number() {
+        let trainer_gallery = selector(json!({ "range": ["TG01", "TG30"] }));
+        assert!(trainer_gallery.matches(&numbered("TG01")));
+        assert!(trainer_gallery.matches(&numbered("TG30")));
+        assert!(!trainer_gallery.matches(&numbered("TG31")));
+        assert!(!trainer_gallery.matches(&numbered("GG01")));
+        assert!(!trainer_gallery.matches(&numbered("15")));
+
+        let galarian_gallery = selector(json!({ "prefix": "GG" }));
+        assert!(galarian_gallery.matches(&numbered("GG70")));
+        assert!(!galarian_gallery.matches(&numbered("TG01")));
+
+        assert_eq!(split_number("TG07"), Some(("TG", 7)));
+        assert_eq!(split_number("123"), Some(("", 123)));
+        assert_eq!(split_number("SV"), None);
+        assert_eq!(split_number("SV1a"), None);
+    }
+
+    #[test]
+    fn select_cards_by_fields() {
+        let card = Card {
+            rarity: Some("Rare Holo V".to_string()),
+            subtypes: Some(vec!["Basic".to_string(), "V".to_string()]),
+            ..numbered("GG44")
+        };
+        assert!(selector(json!({ "rarity": "Rare Holo V" })).matches(&card));
+        assert!(selector(json!({ "subtype": "V", "prefix": "GG" })).matches(&card));
+        assert!(!selector(json!({ "subtype": "VMAX" })).matches(&card));
+        // Every criterion given has to match
+        assert!(!selector(json!({ "rarity": "Rare Holo V", "prefix": "TG" })).matches(&card));
+
+        assert!(selector(json!({})).check().is_err());
+        assert!(selector(json!({ "range": ["TG30", "TG01"] }))
+            .check()
+            .is_err());
+        assert!(selector(json!({ "range": ["TG01", "GG30"] }))
+            .check()
+            .is_err());
+    }
+
+    fn lost_origin() -> BTreeMap<String, Vec<Card>> {
+        sets(&[(
+            "Lost Origin",
+            &[
+                ("1", "Bulbasaur"),
+                ("TG01", "Braixen"),
+                ("2", "Ivysaur"),
+                ("TG02", "Milotic"),
+            ],
+        )])
+    }
+
+    #[test]
+    fn take_selected_cards_out_of_their_set() {
+        let mut sets = lost_origin();
+        apply(
+            json!({
+                "op": "extract",
+                "name": "Lost Origin Trainer Gallery",
+                "cards": [{ "from": "Lost Origin", "select": { "prefix": "TG" }, "take": true }],
+            }),
+            &mut sets,
+        )
+        .unwrap();
+        assert_eq!(
+            cards(&sets, "Lost Origin Trainer Gallery"),
+            vec![("TG01", "Braixen"), ("TG02", "Milotic")]
+        );
+        assert_eq!(
+            cards(&sets, "Lost Origin"),
+            vec![("1", "Bulbasaur"), ("2", "Ivysaur")]
+        );
+
+        // Without take the cards are copied
+        let mut sets = lost_origin();
+        apply(
+            json!({
+                "op": "extract",
+                "name": "Gallery",
+                "cards": [{ "from": "Lost Origin", "select": { "prefix": "TG" } }],
+            }),
+            &mut sets,
+        )
+        .unwrap();
+        assert_eq!(sets["Lost Origin"].len(), 4);
+    }
+
+    #[test]
+    fn reject_positions_that_shifted_or_dont_exist() {
+        let after_take = json!({
+            "op": "extract",
+            "name": "Gallery",
+            "count": 1,
+            "cards": [
+                { "from": "Lost Origin", "select": { "prefix": "TG" }, "take": true },
+                { "from": "Lost Origin", "numbers": { "0": "1" } },
+            ],
+        });
+        let error = apply(after_take.clone(), &mut lost_origin()).unwrap_err();
+        assert!(error.to_string().contains("by position after taking"));
+        assert_eq!(
+            operations(json!([after_take])).validate(&available(&[("Lost Origin", Some(4))])),
+            vec![MappingProblem::PositionsAfterTake {
+                set: "Gallery".to_string(),
+                from: "Lost Origin".to_string()
+            }]
+        );
+
+        let past_the_end = json!({
+            "op": "extract",
+            "name": "Gallery",
+            "count": 1,
+            "cards": [{ "from": "Lost Origin", "numbers": { "0": "4" } }],
+        });
+        let error = apply(past_the_end, &mut lost_origin()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Extracting Gallery takes index 4 from Lost Origin which only has 4 cards"
+        );
+        let past_the_slots = json!({
+            "op": "extract",
+            "name": "Gallery",
+            "count": 1,
+            "cards": [{ "from": "Lost Origin", "numbers": { "1": "0" } }],
+        });
+        let error = apply(past_the_slots, &mut lost_origin()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Extracting Gallery fills index 1 but the set only has 1 cards"
+        );
+    }
+
+    fn describe(operation: Value, sets: &mut BTreeMap<String, Vec<Card>>) -> Vec<String> {
+        serde_json::from_value::<Operation>(operation)
+            .unwrap()
+            .apply_and_describe(sets)
+            .unwrap()
+    }
+
+    #[test]
+    fn describe_the_effects_of_operations() {
+        let mut current = base_and_promos();
+        current.extend(sets(&[("Jungle", &[("1", "Clefable")])]));
+        assert_eq!(
+            describe(
+                json!({ "op": "move", "from": "Base", "to": "Promos", "numbers": ["SV001"] }),
+                &mut current
+            ),
+            vec!["Base: 3 -> 2 cards", "Promos: 1 -> 2 cards"]
+        );
+        assert_eq!(
+            describe(
+                json!({ "op": "merge", "from": "Promos", "into": "Jungle" }),
+                &mut current
+            ),
+            vec!["Jungle: 1 -> 3 cards", "removed Promos (2 cards)"]
+        );
+        assert_eq!(
+            describe(
+                json!({ "op": "map", "from": "Base", "to": "Base Set" }),
+                &mut current
+            ),
+            vec!["renamed Base to Base Set (2 cards)"]
+        );
+        assert_eq!(
+            describe(
+                json!({ "op": "renumber", "set": "Jungle", "numbers": { "SV001": "SV1" } }),
+                &mut current
+            ),
+            vec!["Jungle: 1 cards renumbered or renamed"]
+        );
+    }
 }
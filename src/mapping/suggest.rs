@@ -0,0 +1,327 @@
+use super::Operation;
+use crate::ptcgio_data::Card;
+use itertools::Itertools;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use time::macros::format_description;
+use time::Date;
+
+/// Pairs scoring below this are not suggested
+const MIN_SCORE: f64 = 0.5;
+
+/// Release dates further apart than this many days don't count towards a pair at all
+const RELEASE_WINDOW_DAYS: f64 = 180.0;
+
+/// Series abbreviations both sites use in set names, expanded before names are compared
+const ABBREVIATIONS: [(&str, &str); 6] = [
+    ("swsh", "sword and shield"),
+    ("sm", "sun and moon"),
+    ("bw", "black and white"),
+    ("hgss", "heartgold and soulsilver"),
+    ("hs", "heartgold and soulsilver"),
+    ("dp", "diamond and pearl"),
+];
+
+/// What is known about a set that only one of the datasets has
+pub struct SetProfile {
+    pub name: String,
+    pub code: Option<String>,
+    pub series: Option<String>,
+    released: Option<Date>,
+    card_count: usize,
+    card_names: BTreeSet<String>,
+}
+
+impl SetProfile {
+    /// `released` is in ptcgio's `YYYY/MM/DD`, dates in any other form are left out
+    pub fn new(
+        name: &str,
+        code: Option<String>,
+        series: Option<String>,
+        released: Option<&str>,
+        cards: &[Card],
+    ) -> SetProfile {
+        let format = format_description!("[year]/[month]/[day]");
+        SetProfile {
+            name: name.to_string(),
+            code,
+            series,
+            released: released.and_then(|released| Date::parse(released, format).ok()),
+            card_count: cards.len(),
+            card_names: cards.iter().map(|card| card.name.clone()).collect(),
+        }
+    }
+}
+
+/// The similarity of a pair of sets per signal, each between 0 and 1. Signals one of the sets
+/// has no data for are left out of the overall score.
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Scores {
+    name: f64,
+    card_names: f64,
+    card_count: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    series: Option<f64>,
+    /// 1 for sets released the same day, down to 0 half a year apart
+    #[serde(skip_serializing_if = "Option::is_none")]
+    released: Option<f64>,
+}
+
+impl Scores {
+    fn between(ptcgio: &SetProfile, pkmn: &SetProfile) -> Scores {
+        let code = ptcgio
+            .code
+            .as_ref()
+            .zip(pkmn.code.as_ref())
+            .map(|(left, right)| f64::from(u8::from(left.eq_ignore_ascii_case(right))));
+        let series = ptcgio
+            .series
+            .as_ref()
+            .zip(pkmn.series.as_ref())
+            .map(|(left, right)| name_similarity(left, right));
+        let released = ptcgio.released.zip(pkmn.released).map(|(left, right)| {
+            let days = (left - right).whole_days().abs() as f64;
+            (1.0 - days / RELEASE_WINDOW_DAYS).max(0.0)
+        });
+        let card_count = if ptcgio.card_count.max(pkmn.card_count) == 0 {
+            0.0
+        } else {
+            ptcgio.card_count.min(pkmn.card_count) as f64
+                / ptcgio.card_count.max(pkmn.card_count) as f64
+        };
+        let shared = ptcgio.card_names.intersection(&pkmn.card_names).count();
+        let card_names = if shared == 0 {
+            0.0
+        } else {
+            shared as f64 / ptcgio.card_names.union(&pkmn.card_names).count() as f64
+        };
+
+        Scores {
+            name: name_similarity(&ptcgio.name, &pkmn.name),
+            card_names,
+            card_count,
+            code,
+            series,
+            released,
+        }
+    }
+
+    /// The weighted mean of the signals there are
+    fn overall(&self) -> f64 {
+        let weighted = [
+            (Some(self.name), 0.35),
+            (Some(self.card_names), 0.35),
+            (Some(self.card_count), 0.1),
+            (self.code, 0.1),
+            (self.series, 0.1),
+            (self.released, 0.1),
+        ];
+        let (total, weights) = weighted
+            .iter()
+            .filter_map(|(score, weight)| score.map(|score| (score * weight, *weight)))
+            .fold((0.0, 0.0), |(total, weights), (score, weight)| {
+                (total + score, weights + weight)
+            });
+        total / weights
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Suggestion {
+    pub ptcgio: String,
+    pub pkmn: String,
+    pub score: f64,
+    pub scores: Scores,
+}
+
+/// Suggestions in the form of the mapping file, ready to be added to the ptcgio operations,
+/// and the scores behind each of them
+#[derive(Serialize, JsonSchema)]
+pub struct MappingSuggestions {
+    pub ptcgio: Vec<Operation>,
+    pub scores: Vec<Suggestion>,
+}
+
+/// Scores every pair of a ptcgio-only and a pkmn-only set and pairs them up best first, each
+/// set at most once
+pub fn suggest(ptcgio_sets: &[SetProfile], pkmn_sets: &[SetProfile]) -> MappingSuggestions {
+    let candidates = ptcgio_sets
+        .iter()
+        .cartesian_product(pkmn_sets)
+        .map(|(ptcgio, pkmn)| {
+            let scores = Scores::between(ptcgio, pkmn);
+            (scores.overall(), ptcgio, pkmn, scores)
+        })
+        .filter(|(score, ..)| *score >= MIN_SCORE)
+        .sorted_by(|(left, ..), (right, ..)| right.total_cmp(left));
+
+    let mut paired_ptcgio = BTreeSet::new();
+    let mut paired_pkmn = BTreeSet::new();
+    let mut scores = Vec::new();
+    for (score, ptcgio, pkmn, pair_scores) in candidates {
+        if paired_ptcgio.contains(&ptcgio.name) || paired_pkmn.contains(&pkmn.name) {
+            continue;
+        }
+        paired_ptcgio.insert(&ptcgio.name);
+        paired_pkmn.insert(&pkmn.name);
+        scores.push(Suggestion {
+            ptcgio: ptcgio.name.clone(),
+            pkmn: pkmn.name.clone(),
+            score: (score * 1000.0).round() / 1000.0,
+            scores: pair_scores,
+        });
+    }
+
+    MappingSuggestions {
+        ptcgio: scores
+            .iter()
+            .map(|suggestion| Operation::Map {
+                from: suggestion.ptcgio.clone(),
+                to: suggestion.pkmn.clone(),
+            })
+            .collect(),
+        scores,
+    }
+}
+
+/// Lowercases a set name, spells out `&` and series abbreviations and drops punctuation and
+/// filler words like "Black Star" and "Pokémon"
+fn normalize(name: &str) -> String {
+    let name = name
+        .to_lowercase()
+        .replace('&', " and ")
+        .replace('é', "e")
+        .replace(|c: char| !c.is_alphanumeric(), " ");
+    name.split_whitespace()
+        .map(|word| {
+            ABBREVIATIONS
+                .iter()
+                .find(|(abbreviation, _)| *abbreviation == word)
+                .map_or(word, |(_, expanded)| expanded)
+        })
+        .filter(|word| !["pokemon", "set"].contains(word))
+        .join(" ")
+        .replace("black star ", "")
+}
+
+/// The Dice coefficient of the character bigrams of both normalized names. Names like
+/// "HS—Triumphant" are also compared by the part after the dash.
+fn name_similarity(left: &str, right: &str) -> f64 {
+    let last_part = |name: &str| {
+        name.rsplit(['—', ':'])
+            .next()
+            .map(|part| part.trim().to_string())
+            .unwrap_or_default()
+    };
+    [
+        (left.to_string(), right.to_string()),
+        (last_part(left), right.to_string()),
+        (left.to_string(), last_part(right)),
+    ]
+    .iter()
+    .map(|(left, right)| dice(&normalize(left), &normalize(right)))
+    .fold(0.0, f64::max)
+}
+
+fn dice(left: &str, right: &str) -> f64 {
+    let bigrams = |text: &str| text.chars().tuple_windows::<(char, char)>().collect_vec();
+    let left = bigrams(left);
+    let mut right = bigrams(right);
+    if left.is_empty() || right.is_empty() {
+        return 0.0;
+    }
+    let total = left.len() + right.len();
+    let mut shared = 0;
+    for bigram in left {
+        if let Some(idx) = right.iter().position(|other| *other == bigram) {
+            right.swap_remove(idx);
+            shared += 1;
+        }
+    }
+    2.0 * shared as f64 / total as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(name: &str, released: &str, card_names: &[&str]) -> SetProfile {
+        let cards = card_names
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| Card::stub(&format!("set-{}", idx + 1), name))
+            .collect_vec();
+        SetProfile::new(name, None, None, Some(released), &cards)
+    }
+
+    #[test]
+    fn compare_names_past_series_prefixes() {
+        assert_eq!(name_similarity("HS—Triumphant", "Triumphant"), 1.0);
+        assert_eq!(
+            name_similarity("SM Black Star Promos", "Sun & Moon Promos"),
+            1.0
+        );
+        assert!(name_similarity("Jungle", "Team Rocket Returns") < 0.2);
+    }
+
+    #[test]
+    fn score_release_dates() {
+        let triumphant = profile("HS—Triumphant", "2010/11/03", &[]);
+        let same_day = Scores::between(&triumphant, &profile("Triumphant", "2010/11/03", &[]));
+        assert_eq!(same_day.released, Some(1.0));
+        let months_apart = Scores::between(&triumphant, &profile("Jungle", "2011/02/01", &[]));
+        assert_eq!(months_apart.released, Some(0.5));
+        let years_apart = Scores::between(&triumphant, &profile("Jungle", "1999/06/16", &[]));
+        assert_eq!(years_apart.released, Some(0.0));
+        let undated = SetProfile::new("Triumphant", None, None, Some("Nov 3, 2010"), &[]);
+        assert_eq!(Scores::between(&triumphant, &undated).released, None);
+    }
+
+    #[test]
+    fn suggest_strong_pairs_only() {
+        let ptcgio = [
+            profile(
+                "HS—Triumphant",
+                "2010/11/03",
+                &["Celebi & Venusaur", "Mew", "Rattata"],
+            ),
+            profile(
+                "Jungle",
+                "1999/06/16",
+                &["Clefable", "Electrode", "Flareon"],
+            ),
+        ];
+        let pkmn = [
+            profile(
+                "Triumphant",
+                "2010/11/03",
+                &["Celebi & Venusaur", "Mew", "Rattata"],
+            ),
+            profile(
+                "Team Rocket Returns",
+                "2004/11/08",
+                &["Dark Dragonite", "Rocket's Admin."],
+            ),
+        ];
+        let suggestions = suggest(&ptcgio, &pkmn);
+        let pairs = suggestions
+            .scores
+            .iter()
+            .map(|suggestion| (suggestion.ptcgio.as_str(), suggestion.pkmn.as_str()))
+            .collect_vec();
+        assert_eq!(pairs, vec![("HS—Triumphant", "Triumphant")]);
+        assert_eq!(suggestions.scores[0].score, 1.0);
+        assert!(
+            Scores::between(&ptcgio[1], &pkmn[1]).overall() < MIN_SCORE,
+            "Jungle and Team Rocket Returns are left unpaired for scoring too low"
+        );
+        assert!(matches!(
+            &suggestions.ptcgio[..],
+            [Operation::Map { from, to }] if from == "HS—Triumphant" && to == "Triumphant"
+        ));
+    }
+}
@@ -0,0 +1,250 @@
+use crate::decisions::{Decision, Decisions};
+use crate::diff::{parse_path, set_at_path, Discrepancy};
+use crate::ptcgio_data::Data;
+use crate::reconcile::set_id_of;
+use anyhow::{anyhow, Context, Result};
+use git2::{Oid, Repository};
+use itertools::Itertools;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Whether a discrepancy should be applied, either because a reviewer decided on the pkmncards
+/// value or because it's in one of `fields`. Pkmncards leaves fields like flavor text out of
+/// many pages, so a value it lacks is only deleted from ptcgio when a reviewer accepted that.
+pub fn accepts(discrepancy: &Discrepancy, decisions: &Decisions, fields: &[String]) -> bool {
+    match decisions.get(&discrepancy.set, &discrepancy.number, &discrepancy.path) {
+        Some(Decision::AcceptRight | Decision::Override(_)) => true,
+        Some(Decision::AcceptLeft | Decision::Ignore) => false,
+        None => {
+            !discrepancy.right.is_null() && fields.iter().any(|field| field == discrepancy.field())
+        }
+    }
+}
+
+/// Rewrites the card files of a pokemon-tcg-data checkout so each discrepancy takes the
+/// pkmncards value. Cards are edited as raw JSON so key order, absent fields and formatting
+/// stay as they were and the resulting git diff only holds the corrected values.
+///
+/// Returns the changed files relative to the checkout.
+pub async fn apply(data: &Data, discrepancies: &[Discrepancy]) -> Result<Vec<PathBuf>> {
+    let set_ids = data
+        .sets
+        .iter()
+        .map(|set| set.id.as_str())
+        .collect::<HashSet<&str>>();
+    let by_set = discrepancies
+        .iter()
+        .map(|discrepancy| {
+            let set_id = set_id_of(&discrepancy.id, &set_ids).ok_or(anyhow!(
+                "Could not find the set the card {} belongs to",
+                discrepancy.id
+            ))?;
+            Ok((set_id, discrepancy))
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .into_group_map();
+
+    let mut changed = Vec::new();
+    for (set_id, set_discrepancies) in by_set.into_iter().sorted_by_key(|(set_id, _)| *set_id) {
+        let relative = Path::new("cards/en").join(set_id).with_extension("json");
+        let path = data.path().join(&relative);
+        let original = tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut cards: Vec<Value> = serde_json::from_str(&original)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+        for discrepancy in set_discrepancies {
+            let card = cards
+                .iter_mut()
+                .find(|card| card.get("id").and_then(Value::as_str) == Some(&discrepancy.id))
+                .ok_or(anyhow!(
+                    "The card {} is not in {}",
+                    discrepancy.id,
+                    path.display()
+                ))?;
+            set_at_path(
+                card,
+                &parse_path(&discrepancy.path)?,
+                discrepancy.right.clone(),
+            )
+            .with_context(|| {
+                format!("Failed to patch {} of {}", discrepancy.path, discrepancy.id)
+            })?;
+        }
+
+        let mut output = serde_json::to_string_pretty(&cards)?;
+        if original.ends_with('\n') {
+            output.push('\n');
+        }
+        if output != original {
+            tokio::fs::write(&path, output).await?;
+            changed.push(relative);
+        }
+    }
+    Ok(changed)
+}
+
+/// Commits the given files of the checkout on top of its current HEAD
+pub fn commit(checkout: &Path, files: &[PathBuf], message: &str) -> Result<Oid> {
+    let repo = Repository::open(checkout).context("Failed to open the checkout")?;
+    let mut index = repo.index()?;
+    for file in files {
+        index.add_path(file)?;
+    }
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let signature = repo
+        .signature()
+        .context("No git identity is configured for the checkout")?;
+    let parent = repo.head()?.peel_to_commit()?;
+    Ok(repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &[&parent],
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn discrepancy(path: &str, right: Value) -> Discrepancy {
+        Discrepancy {
+            set: "Base".to_string(),
+            number: "1".to_string(),
+            id: "base1-1".to_string(),
+            path: path.to_string(),
+            left: json!("ptcgio"),
+            right,
+        }
+    }
+
+    #[test]
+    fn accept_listed_fields_and_decided_discrepancies() {
+        let fields = vec![
+            "attacks".to_string(),
+            "flavorText".to_string(),
+            "rules".to_string(),
+        ];
+        let none = Decisions::default();
+        let decisions: Decisions = serde_json::from_value(json!({"Base": {"1": {
+            "hp": "accept-right",
+            "rules": "accept-left",
+            "flavorText": "accept-right",
+            "level": {"override": null},
+        }}}))
+        .unwrap();
+
+        assert!(accepts(
+            &discrepancy("attacks[0].text", json!("Flip a coin.")),
+            &none,
+            &fields
+        ));
+        assert!(!accepts(&discrepancy("hp", json!("80")), &none, &fields));
+        assert!(accepts(
+            &discrepancy("hp", json!("80")),
+            &decisions,
+            &fields
+        ));
+        assert!(!accepts(
+            &discrepancy("rules", json!(["A rule"])),
+            &decisions,
+            &fields
+        ));
+
+        assert!(!accepts(
+            &discrepancy("flavorText", Value::Null),
+            &none,
+            &fields
+        ));
+        assert!(accepts(
+            &discrepancy("flavorText", Value::Null),
+            &decisions,
+            &fields
+        ));
+        assert!(accepts(
+            &discrepancy("level", Value::Null),
+            &decisions,
+            &fields
+        ));
+    }
+
+    #[tokio::test]
+    async fn only_change_the_patched_values() {
+        let checkout = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(checkout.path().join("sets")).unwrap();
+        std::fs::create_dir_all(checkout.path().join("cards/en")).unwrap();
+        let sets = json!([{
+            "id": "base1",
+            "name": "Base",
+            "series": "Base",
+            "printedTotal": 102,
+            "total": 102,
+            "legalities": {},
+            "releaseDate": "1999/01/09",
+            "updatedAt": "2020/08/14 09:35:00",
+            "images": {},
+        }]);
+        std::fs::write(checkout.path().join("sets/en.json"), sets.to_string()).unwrap();
+        let original = r#"[
+  {
+    "id": "base1-1",
+    "name": "Alakazam",
+    "number": "1",
+    "supertype": "Pokémon",
+    "hp": "80",
+    "flavorText": "Its brain can outperform a supercomputer.",
+    "legalities": {},
+    "images": {}
+  },
+  {
+    "id": "base1-2",
+    "name": "Blastoise",
+    "number": "2",
+    "supertype": "Pokémon",
+    "legalities": {},
+    "images": {}
+  }
+]
+"#;
+        let cards_path = checkout.path().join("cards/en/base1.json");
+        std::fs::write(&cards_path, original).unwrap();
+        let data = Data::open(checkout.path()).unwrap();
+
+        let changed = apply(
+            &data,
+            &[
+                discrepancy("hp", json!("90")),
+                discrepancy("flavorText", Value::Null),
+                discrepancy("artist", json!("Ken Sugimori")),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(changed, vec![PathBuf::from("cards/en/base1.json")]);
+        let patched = original
+            .replace("\"80\"", "\"90\"")
+            .replace(
+                "    \"flavorText\": \"Its brain can outperform a supercomputer.\",\n",
+                "",
+            )
+            .replacen(
+                "\"images\": {}\n",
+                "\"images\": {},\n    \"artist\": \"Ken Sugimori\"\n",
+                1,
+            );
+        assert_eq!(std::fs::read_to_string(&cards_path).unwrap(), patched);
+
+        assert!(apply(&data, &[discrepancy("hp", json!("90"))])
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
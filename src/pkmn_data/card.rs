@@ -1,8 +1,10 @@
+use crate::pkmn_data::drift::VocabularyDrift;
 use crate::pkmn_data::extractors::{
     clean_text, direct_text_skip_past, extract_text, select_element, select_number,
     select_opt_element, select_opt_text, select_text,
 };
-use crate::ptcgio_data::Card;
+use crate::pkmn_data::rich_text::{RichText, TextFormat, ENERGY_SYMBOLS};
+use crate::ptcgio_data::{Card, Damage, EnergyColor, EnergyCost};
 use anyhow::{anyhow, bail, Context, Error, Result};
 use ego_tree::NodeRef;
 use itertools::Itertools;
@@ -17,26 +19,38 @@ use std::collections::{BTreeMap, HashSet};
 use std::iter;
 use std::ops::Deref;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use strum::{Display, EnumString};
 use time::macros::format_description;
 use time::Date;
 
 pub(super) struct CardFetcher {
-    url: String,
+    pub(super) url: String,
     client: ClientWithMiddleware,
+    text_format: TextFormat,
+    drift: Arc<Mutex<VocabularyDrift>>,
 }
 
 impl CardFetcher {
-    pub(super) fn new(card_ref: ElementRef, client: &ClientWithMiddleware) -> Self {
+    pub(super) fn new(
+        card_ref: ElementRef,
+        client: &ClientWithMiddleware,
+        text_format: TextFormat,
+        drift: &Arc<Mutex<VocabularyDrift>>,
+    ) -> Self {
         let url = card_ref.value().attr("href").unwrap().to_string();
         log::trace!("url for card: {}", url);
         Self {
             url,
             client: client.clone(),
+            text_format,
+            drift: drift.clone(),
         }
     }
 
-    pub(super) async fn fetch(&self) -> Result<Card> {
+    /// The card on the page with the date pkmncards says it was released, in ptcgio's
+    /// `YYYY/MM/DD`
+    pub(super) async fn fetch(&self) -> Result<(Card, String)> {
         let response = self.client.get(&self.url).send().await?;
         let card_page = if response.status().is_success() {
             response.text().await?
@@ -47,16 +61,108 @@ impl CardFetcher {
                 response.status()
             )
         };
-        let entry_selector = Selector::parse("div.entry-content").unwrap();
-        let replaced_ptcg_symbols = replace_ptcg_symbols(Html::parse_document(&card_page))?;
-        let elem = replaced_ptcg_symbols
-            .select(&entry_selector)
-            .next()
-            .ok_or(anyhow!("Could not retrieve page for {}", self.url))?;
-        let card = CardText::parse(elem).with_context(|| format!("Failed to parse webpage: {}\n ##################################################################################\n{}\n##################################################################################", &self.url, elem.html()))?.try_into()?;
+        let document = replace_ptcg_symbols(Html::parse_document(&card_page))?;
+        let elem = entry_content(&document)
+            .with_context(|| format!("Could not retrieve page for {}", self.url))?;
+        let mut card_text = CardText::parse(elem).with_context(|| format!("Failed to parse webpage: {}\n ##################################################################################\n{}\n##################################################################################", &self.url, elem.html()))?;
+        {
+            let mut drift = self.drift.lock().unwrap();
+            for (kind, value) in card_text.unknown_vocabulary() {
+                log::warn!("Unknown {} \"{}\" on {}", kind, value, &self.url);
+                drift.record(kind, &value);
+            }
+        }
+        if self.text_format != TextFormat::Plain {
+            card_text
+                .all_text_info
+                .render_texts(elem, self.text_format)
+                .with_context(|| format!("Failed to render the card text of {}", &self.url))?;
+        }
+        let more_evolves_at = card_text
+            .type_evolves_is
+            .evolves
+            .as_mut()
+            .and_then(|evolves| evolves.more_at.take());
+        let released = card_text.release_meta.date_released;
+        let released = format!(
+            "{}/{:02}/{:02}",
+            released.year(),
+            u8::from(released.month()),
+            released.day()
+        );
+        let mut card: Card = card_text.try_into()?;
+        if let Some(more_evolves_at) = more_evolves_at {
+            let evolves_to = self
+                .fetch_evolves_to(&more_evolves_at, &card.name)
+                .await
+                .with_context(|| format!("Failed to complete the evolves of {}", &self.url))?;
+            // Keeps the order of the names the card lists itself
+            let to = card.evolves_to.get_or_insert_with(Vec::new);
+            for name in evolves_to {
+                if !to.contains(&name) {
+                    to.push(name);
+                }
+            }
+            card.enriched.push("evolvesTo".to_string());
+        }
 
-        Ok(card)
+        Ok((card, released))
     }
+
+    /// The names of the cards listed at `url`, and the pages after it, that evolve from `name`
+    async fn fetch_evolves_to(&self, url: &str, name: &str) -> Result<Vec<String>> {
+        let result_selector = Selector::parse("article.type-pkmn_card").unwrap();
+        let name_selector = Selector::parse("div.name-hp-color span.name").unwrap();
+        let evolves_selector = Selector::parse("span.evolves").unwrap();
+        let next_selector = Selector::parse("a.next.page-numbers").unwrap();
+
+        let mut names = Vec::new();
+        let mut next_page = Some(url.to_string());
+        while let Some(url) = next_page.take() {
+            let response = self.client.get(&url).send().await?;
+            let page = if response.status().is_success() {
+                response.text().await?
+            } else {
+                bail!(
+                    "Failed to get evolutions from {}: {}",
+                    url,
+                    response.status()
+                )
+            };
+            let doc = replace_ptcg_symbols(Html::parse_document(&page))?;
+            for result in doc.select(&result_selector) {
+                let evolves = select_opt_element(result, evolves_selector.clone())
+                    .map(Evolves::parse)
+                    .transpose()?;
+                if evolves.is_some_and(|evolves| evolves.from.iter().any(|from| from == name)) {
+                    let evolution = select_text(result, name_selector.clone())?;
+                    if !names.contains(&evolution) {
+                        names.push(evolution);
+                    }
+                }
+            }
+            next_page = doc
+                .select(&next_selector)
+                .next()
+                .and_then(|next| next.value().attr("href"))
+                .map(str::to_string);
+        }
+        Ok(names)
+    }
+}
+
+/// Reads the card of a pkmncards card page as it is served, without following any of its links
+pub fn parse_card_page(page: &str) -> Result<Card> {
+    let document = replace_ptcg_symbols(Html::parse_document(page))?;
+    CardText::parse(entry_content(&document)?)?.try_into()
+}
+
+fn entry_content(document: &Html) -> Result<ElementRef<'_>> {
+    let entry_selector = Selector::parse("div.entry-content").unwrap();
+    document
+        .select(&entry_selector)
+        .next()
+        .ok_or(anyhow!("The page has no card entry"))
 }
 
 fn replace_ptcg_symbols(mut html: Html) -> Result<Html> {
@@ -73,20 +179,13 @@ fn replace_ptcg_symbols(mut html: Html) -> Result<Html> {
             .ok_or(anyhow!("Failed to get node mutator from tree"))?;
         if symbol_node.parent().is_some() {
             let new_text = Node::Text(Text {
-                text: StrTendril::from(match text.as_str() {
-                    "{G}" => "Grass",
-                    "{W}" => "Water",
-                    "{R}" => "Fire",
-                    "{L}" => "Lightning",
-                    "{P}" => "Psychic",
-                    "{F}" => "Fighting",
-                    "{D}" => "Darkness",
-                    "{M}" => "Metal",
-                    "{Y}" => "Fairy",
-                    "{N}" => "Dragon",
-                    "{C}" => "Colorless",
-                    _ => bail!("Invalid value in symbol node"),
-                }),
+                text: StrTendril::from(
+                    ENERGY_SYMBOLS
+                        .iter()
+                        .find(|(symbol, _)| *symbol == text)
+                        .map(|(_, name)| *name)
+                        .ok_or(anyhow!("Invalid value in symbol node"))?,
+                ),
             });
 
             let mut text_node = symbol_node.append(new_text);
@@ -143,8 +242,8 @@ impl TryFrom<CardText> for Card {
                 .rules
                 .iter()
                 .map(|rule| match rule {
-                    Rule::RuleBox { purpose, text } => format!("{} rule: {}", purpose, text),
-                    Rule::NonRuleBox { text, .. } => text.to_string(),
+                    Rule::Boxed { purpose, text } => format!("{} rule: {}", purpose, text),
+                    Rule::Unboxed { text, .. } | Rule::Tera { text } => text.to_string(),
                 })
                 .chain(value.all_text_info.text_infos.iter().filter_map(
                     |text_info| match text_info {
@@ -159,21 +258,12 @@ impl TryFrom<CardText> for Card {
             .all_text_info
             .text_infos
             .iter()
-            .filter_map(|ability| match ability {
-                TextInfo::Attack {
-                    cost,
-                    name,
-                    damage,
-                    text,
-                } => Some(BTreeMap::from([
+            .filter_map(|text_info| text_info.get_attack())
+            .map(|(cost, name, damage, text)| {
+                BTreeMap::from([
                     (
                         "cost".to_string(),
-                        serde_json::Value::Array(
-                            cost.iter()
-                                .map(EnergyColor::to_string)
-                                .map(serde_json::Value::String)
-                                .collect::<Vec<serde_json::Value>>(),
-                        ),
+                        serde_json::Value::Array(cost.to_ptcgio()),
                     ),
                     (
                         "name".to_string(),
@@ -181,7 +271,9 @@ impl TryFrom<CardText> for Card {
                     ),
                     (
                         "damage".to_string(),
-                        serde_json::Value::String(damage.clone().unwrap_or("".to_string())),
+                        serde_json::Value::String(
+                            damage.as_ref().map(Damage::to_string).unwrap_or_default(),
+                        ),
                     ),
                     (
                         "text".to_string(),
@@ -189,10 +281,9 @@ impl TryFrom<CardText> for Card {
                     ),
                     (
                         "convertedEnergyCost".to_string(),
-                        serde_json::Value::Number(cost.len().into()),
+                        serde_json::Value::Number(cost.total().into()),
                     ),
-                ])),
-                _ => None,
+                ])
             })
             .collect::<Vec<BTreeMap<String, serde_json::Value>>>();
 
@@ -258,7 +349,15 @@ impl TryFrom<CardText> for Card {
             Vec::new()
         };
 
-        subtypes.extend(value.type_evolves_is.is.iter().map(|tag| tag.to_string()));
+        // Sorted since the tags are gathered in a set
+        subtypes.extend(
+            value
+                .type_evolves_is
+                .is
+                .iter()
+                .sorted()
+                .map(|tag| tag.to_string()),
+        );
         subtypes.extend(value.type_evolves_is.stage.map(|stage| stage.to_string()));
         let subtypes = if subtypes.is_empty() {
             None
@@ -320,6 +419,7 @@ impl TryFrom<CardText> for Card {
                 .mark_formats
                 .and_then(|mark| mark.mark)
                 .map(|mark| mark.to_string()),
+            enriched: Vec::new(),
         })
     }
 }
@@ -447,6 +547,75 @@ impl PkmnParse for CardText {
     }
 }
 
+impl CardText {
+    /// The values of the card the parser has no variant for, by the kind of value they are
+    fn unknown_vocabulary(&self) -> Vec<(&'static str, String)> {
+        let type_evolves_is = &self.type_evolves_is;
+        let subtypes = type_evolves_is
+            .pkmn_subtype
+            .iter()
+            .filter_map(|subtype| match subtype {
+                PkmnSubtype::Unknown(value) => Some(("subtype", value.clone())),
+                _ => None,
+            });
+        let subsubtypes = type_evolves_is
+            .pkmn_subsubtype
+            .iter()
+            .filter_map(|subsubtype| match subsubtype {
+                PkmnSubSubType::Unknown(value) => Some(("subsubtype", value.clone())),
+                _ => None,
+            });
+        let stages = type_evolves_is
+            .stage
+            .iter()
+            .filter_map(|stage| match stage {
+                Stage::Unknown(value) => Some(("stage", value.clone())),
+                _ => None,
+            });
+        let tags = type_evolves_is.is.iter().filter_map(|tag| match tag {
+            PtcgTag::Unknown(value) => Some(("tag", value.clone())),
+            _ => None,
+        });
+        let marks = self
+            .mark_formats
+            .iter()
+            .filter_map(|mark_formats| match &mark_formats.mark {
+                Some(Mark::Unknown(value)) => Some(("mark", value.clone())),
+                _ => None,
+            });
+        let colors = self
+            .name_hp_color
+            .color
+            .iter()
+            .flatten()
+            .chain(
+                self.weak_resist_retreat
+                    .iter()
+                    .flat_map(|weak_resist_retreat| {
+                        weak_resist_retreat
+                            .weak
+                            .colors
+                            .iter()
+                            .chain(&weak_resist_retreat.resist.colors)
+                    }),
+            )
+            .filter_map(|color| match color {
+                // Cards without a weakness or resistance have "No Weakness" or "No Resistance"
+                PokeColor::None(value) if !value.starts_with("No ") => {
+                    Some(("color", value.clone()))
+                }
+                _ => None,
+            });
+        subtypes
+            .chain(subsubtypes)
+            .chain(stages)
+            .chain(tags)
+            .chain(marks)
+            .chain(colors)
+            .collect()
+    }
+}
+
 #[derive(Eq, PartialEq, Debug)]
 struct NameHpColor {
     name: String,
@@ -607,6 +776,24 @@ impl PkmnParse for AllTextInfo {
     }
 }
 
+impl AllTextInfo {
+    /// Replaces the plain text of every text info with its rich text rendered as `format`.
+    /// `element` is the card's entry, the one `CardText` is parsed from.
+    fn render_texts(&mut self, element: ElementRef, format: TextFormat) -> Result<()> {
+        let all_text_info_selector = Selector::parse("div.card-tabs > div.tab.text").unwrap();
+        let text_info_selector = Selector::parse("div.text > p").unwrap();
+        let all_text_info = select_element(element, all_text_info_selector)?;
+        for (text_info, paragraph) in self
+            .text_infos
+            .iter_mut()
+            .zip(all_text_info.select(&text_info_selector))
+        {
+            text_info.set_text(TextInfo::rich_text(paragraph)?.render(format));
+        }
+        Ok(())
+    }
+}
+
 #[derive(Eq, PartialEq, Debug)]
 struct DamageModifier {
     colors: Vec<PokeColor>,
@@ -886,6 +1073,8 @@ enum PkmnSubtype {
     Stadium,
     #[strum(serialize = "Special Energy")]
     SpecialEnergy,
+    #[strum(default)]
+    Unknown(String),
 }
 
 #[derive(Eq, PartialEq, Debug, EnumString, Display)]
@@ -898,6 +1087,8 @@ enum PkmnSubSubType {
     GoldenrodGameCorner,
     #[strum(serialize = "Pokémon Tool F")]
     PokemonToolF,
+    #[strum(default)]
+    Unknown(String),
 }
 
 #[derive(Eq, PartialEq, Debug, EnumString, Display)]
@@ -922,12 +1113,47 @@ enum Stage {
     #[strum(serialize = "LEGEND")]
     Legend,
     Restored,
+    #[strum(default)]
+    Unknown(String),
 }
 
 #[derive(Eq, PartialEq, Debug)]
 struct Evolves {
     from: Vec<String>,
     to: Vec<String>,
+    /// Where to find the rest of `to` when pkmncards cut it short with "[…]"
+    more_at: Option<String>,
+}
+
+impl Evolves {
+    /// Marks the point pkmncards cut an evolves-into list at
+    const TRUNCATED: &'static str = "[…]";
+    const EVOLVES_FROM_SEARCH: &'static str = "https://pkmncards.com/?s=evolves-from%3A";
+
+    /// The page listing the rest of a truncated list: the one "[…]" links to or otherwise a
+    /// search for the cards that evolve from the card's Pokémon
+    fn more_at(element: ElementRef) -> Option<String> {
+        let link_selector = Selector::parse("a").unwrap();
+        let link = element
+            .select(&link_selector)
+            .find(|link| extract_text(*link).contains('…'))
+            .and_then(|link| link.value().attr("href"));
+        if let Some(link) = link {
+            return Some(link.to_string());
+        }
+        let species_selector = Selector::parse("span.pokemons > span.pokemon > a").unwrap();
+        let species = element
+            .parent_element()?
+            .select(&species_selector)
+            .next()?
+            .value()
+            .attr("href")?
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()?
+            .to_string();
+        Some(format!("{}{}", Self::EVOLVES_FROM_SEARCH, species))
+    }
 }
 
 impl PkmnParse for Evolves {
@@ -971,147 +1197,155 @@ impl PkmnParse for Evolves {
             Vec::with_capacity(0)
         };
 
-        Ok(Evolves { from, to })
+        let more_at = if text.contains(Self::TRUNCATED) {
+            Some(Self::more_at(element).ok_or(anyhow!("Nowhere to find the rest of: {}", text))?)
+        } else {
+            None
+        };
+
+        Ok(Evolves { from, to, more_at })
     }
 }
 
-#[derive(Eq, PartialEq, Debug, Hash, EnumString, Display)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Hash, EnumString, Display)]
 #[strum(serialize_all = "kebab-case")]
 enum PtcgTag {
-    #[strum(to_string = "V")]
+    #[strum(serialize = "v", to_string = "V")]
     V,
-    #[strum(to_string = "GX")]
+    #[strum(serialize = "gx", to_string = "GX")]
     GX,
     #[strum(serialize = "ex-%e2%86%91", to_string = "EX")]
     ExUpper,
-    #[strum(to_string = "Delta Species")]
+    #[strum(serialize = "delta-species", to_string = "Delta Species")]
     DeltaSpecies,
-    #[strum(to_string = "Rapid Strike")]
+    #[strum(serialize = "rapid-strike", to_string = "Rapid Strike")]
     RapidStrike,
     #[strum(serialize = "ex-%e2%86%93", to_string = "ex")]
     ExLower,
-    #[strum(to_string = "Single Strike")]
+    #[strum(serialize = "single-strike", to_string = "Single Strike")]
     SingleStrike,
-    #[strum(to_string = "Galarian")]
+    #[strum(serialize = "galarian", to_string = "Galarian")]
     Galarian,
-    #[strum(to_string = "TAG TEAM")]
+    #[strum(serialize = "tag-team", to_string = "TAG TEAM")]
     TagTeam,
-    #[strum(to_string = "Dynamax")]
+    #[strum(serialize = "dynamax", to_string = "Dynamax")]
     Dynamax,
-    #[strum(to_string = "Dark")]
+    #[strum(serialize = "dark", to_string = "Dark")]
     Dark,
-    #[strum(to_string = "Team Plasma")]
+    #[strum(serialize = "team-plasma", to_string = "Team Plasma")]
     TeamPlasma,
-    #[strum(to_string = "Ball")]
+    #[strum(serialize = "ball", to_string = "Ball")]
     Ball,
-    #[strum(to_string = "Alolan")]
+    #[strum(serialize = "alolan", to_string = "Alolan")]
     Alolan,
-    #[strum(to_string = "SP")]
+    #[strum(serialize = "sp", to_string = "SP")]
     SP,
-    #[strum(to_string = "Ultra Beast")]
+    #[strum(serialize = "ultra-beast", to_string = "Ultra Beast")]
     UltraBeast,
-    #[strum(to_string = "Dual Type")]
+    #[strum(serialize = "dual-type", to_string = "Dual Type")]
     DualType,
     #[strum(serialize = "ex-3", to_string = "ex")]
     Ex3,
-    #[strum(to_string = "Gigantamax")]
+    #[strum(serialize = "gigantamax", to_string = "Gigantamax")]
     Gigantamax,
-    #[strum(to_string = "Hisuian")]
+    #[strum(serialize = "hisuian", to_string = "Hisuian")]
     Hisuian,
-    #[strum(to_string = "Fusion Strike")]
+    #[strum(serialize = "fusion-strike", to_string = "Fusion Strike")]
     FusionStrike,
-    #[strum(to_string = "Fossil")]
+    #[strum(serialize = "fossil", to_string = "Fossil")]
     Fossil,
-    #[strum(to_string = "Team Aqua's")]
+    #[strum(serialize = "team-aquas", to_string = "Team Aqua's")]
     TeamAquas,
-    #[strum(to_string = "Team Magma's")]
+    #[strum(serialize = "team-magmas", to_string = "Team Magma's")]
     TeamMagmas,
-    #[strum(to_string = "G")]
+    #[strum(serialize = "g", to_string = "G")]
     G,
-    #[strum(to_string = "Prime")]
+    #[strum(serialize = "prime", to_string = "Prime")]
     Prime,
-    #[strum(to_string = "Star")]
+    #[strum(serialize = "star", to_string = "Star")]
     Star,
-    #[strum(to_string = "Brock's")]
+    #[strum(serialize = "brocks", to_string = "Brock's")]
     Brocks,
-    #[strum(to_string = "Team Rocket's")]
+    #[strum(serialize = "team-rockets", to_string = "Team Rocket's")]
     TeamRockets,
-    #[strum(to_string = "Sabrina's")]
+    #[strum(serialize = "sabrinas", to_string = "Sabrina's")]
     Sabrinas,
-    #[strum(to_string = "Prism Star")]
+    #[strum(serialize = "prism-star", to_string = "Prism Star")]
     PrismStar,
-    #[strum(to_string = "Erika's")]
+    #[strum(serialize = "erikas", to_string = "Erika's")]
     Erikas,
-    #[strum(to_string = "Misty's")]
+    #[strum(serialize = "mistys", to_string = "Misty's")]
     Mistys,
-    #[strum(to_string = "Holon")]
+    #[strum(serialize = "holon", to_string = "Holon")]
     Holon,
-    #[strum(to_string = "Blaine's")]
+    #[strum(serialize = "blaines", to_string = "Blaine's")]
     Blaines,
     // Elite 4
-    #[strum(to_string = "E4")]
+    #[strum(serialize = "e4", to_string = "E4")]
     E4,
-    #[strum(to_string = "Lt. Surge's")]
+    #[strum(serialize = "lt-surges", to_string = "Lt. Surge's")]
     LtSurges,
-    #[strum(to_string = "Light")]
+    #[strum(serialize = "light", to_string = "Light")]
     Light,
     // Gym Leader
-    #[strum(to_string = "GL")]
+    #[strum(serialize = "gl", to_string = "GL")]
     GL,
-    #[strum(to_string = "Shining")]
+    #[strum(serialize = "shining", to_string = "Shining")]
     Shining,
-    #[strum(to_string = "Scoop Up")]
+    #[strum(serialize = "scoop-up", to_string = "Scoop Up")]
     ScoopUp,
-    #[strum(to_string = "Berry")]
+    #[strum(serialize = "berry", to_string = "Berry")]
     Berry,
-    #[strum(to_string = "Koga's")]
+    #[strum(serialize = "kogas", to_string = "Koga's")]
     Kogas,
-    #[strum(to_string = "Radiant")]
+    #[strum(serialize = "radiant", to_string = "Radiant")]
     Radiant,
-    #[strum(to_string = "Potion")]
+    #[strum(serialize = "potion", to_string = "Potion")]
     Potion,
-    #[strum(to_string = "C")]
+    #[strum(serialize = "c", to_string = "C")]
     C,
-    #[strum(to_string = "Giovanni's")]
+    #[strum(serialize = "giovannis", to_string = "Giovanni's")]
     Giovannis,
-    #[strum(to_string = "FB")]
+    #[strum(serialize = "fb", to_string = "FB")]
     FB,
-    #[strum(to_string = "ACE SPEC")]
+    #[strum(serialize = "ace-spec", to_string = "ACE SPEC")]
     AceSpec,
-    #[strum(to_string = "Rod")]
+    #[strum(serialize = "rod", to_string = "Rod")]
     Rod,
-    #[strum(to_string = "Crystal")]
+    #[strum(serialize = "crystal", to_string = "Crystal")]
     Crystal,
-    #[strum(to_string = "Tera")]
+    #[strum(serialize = "tera", to_string = "Tera")]
     Tera,
-    #[strum(to_string = "Gloves")]
+    #[strum(serialize = "gloves", to_string = "Gloves")]
     Gloves,
-    #[strum(to_string = "Paldean")]
+    #[strum(serialize = "paldean", to_string = "Paldean")]
     Paldean,
-    #[strum(to_string = "Lucky")]
+    #[strum(serialize = "lucky", to_string = "Lucky")]
     Lucky,
-    #[strum(to_string = "Primal")]
+    #[strum(serialize = "primal", to_string = "Primal")]
     Primal,
-    #[strum(to_string = "Shard")]
+    #[strum(serialize = "shard", to_string = "Shard")]
     Shard,
-    #[strum(to_string = "Plate")]
+    #[strum(serialize = "plate", to_string = "Plate")]
     Plate,
-    #[strum(to_string = "Board")]
+    #[strum(serialize = "board", to_string = "Board")]
     Board,
-    #[strum(to_string = "Eternamax")]
+    #[strum(serialize = "eternamax", to_string = "Eternamax")]
     Eternamax,
-    #[strum(to_string = "Sphere")]
+    #[strum(serialize = "sphere", to_string = "Sphere")]
     Sphere,
-    #[strum(to_string = "Plus")]
+    #[strum(serialize = "plus", to_string = "Plus")]
     Plus,
-    #[strum(to_string = "Broken")]
+    #[strum(serialize = "broken", to_string = "Broken")]
     Broken,
-    #[strum(to_string = "Lance's")]
+    #[strum(serialize = "lances", to_string = "Lance's")]
     Lances,
-    #[strum(to_string = "Imakuni?'s")]
+    #[strum(serialize = "imakunis", to_string = "Imakuni?'s")]
     Imakunis,
-    #[strum(to_string = "Cool")]
+    #[strum(serialize = "cool", to_string = "Cool")]
     Cool,
+    #[strum(default)]
+    Unknown(String),
 }
 
 #[derive(Eq, PartialEq, Debug)]
@@ -1141,11 +1375,20 @@ enum TextInfo {
         text: String,
     },
     Attack {
-        cost: Vec<EnergyColor>,
+        cost: EnergyCost,
         name: String,
-        damage: Option<String>,
+        damage: Option<Damage>,
         text: String,
     },
+    /// An attack that can only be used once per game by any Pokémon-GX
+    GxAttack {
+        cost: EnergyCost,
+        name: String,
+        damage: Option<Damage>,
+        text: String,
+    },
+    /// An ability or attack that can only be used once per game by any Pokémon VSTAR
+    VstarPower(Box<TextInfo>),
     Rule {
         rule: String,
     },
@@ -1162,46 +1405,107 @@ impl TextInfo {
     const POKEMON_POWER: &'static str = "Pokémon Power";
     const ANCIENT_TRAIT: &'static str = "Ancient Trait";
     const HELD_ITEM: &'static str = "Held Item";
+    const VSTAR_POWER: &'static str = "VSTAR Power";
+    const VSTAR_POWER_REMINDER: &'static str = "(You can't use more than 1 VSTAR Power in a game.)";
 
+    /// Makes an ability from the label pkmncards links it with. Labels are compared without
+    /// case, accents, spaces and dashes since older sets spell them "Poké-Body", "Poke-POWER"
+    /// and so on.
     fn make_ability(
         ability_type: &str,
         ability_name: String,
         ability_text: String,
     ) -> Result<Self> {
-        Ok(match ability_type {
-            TextInfo::ABILITY => TextInfo::Ability {
+        let label = ability_type
+            .to_lowercase()
+            .replace('é', "e")
+            .replace(|c: char| c == '-' || c.is_whitespace(), "");
+        Ok(match label.as_str() {
+            "ability" => TextInfo::Ability {
                 name: ability_name,
                 text: ability_text,
             },
-            TextInfo::POKEBODY => TextInfo::PokeBody {
+            "pokebody" => TextInfo::PokeBody {
                 name: ability_name,
                 text: ability_text,
             },
-            TextInfo::POKEPOWER => TextInfo::PokePower {
+            "pokepower" => TextInfo::PokePower {
                 name: ability_name,
                 text: ability_text,
             },
-            TextInfo::POKEMON_POWER => TextInfo::PokemonPower {
+            "pokemonpower" => TextInfo::PokemonPower {
                 name: ability_name,
                 text: ability_text,
             },
-            TextInfo::ANCIENT_TRAIT => TextInfo::AncientTrait {
+            "ancienttrait" => TextInfo::AncientTrait {
                 name: ability_name,
                 text: ability_text,
             },
-            TextInfo::HELD_ITEM => TextInfo::HeldItem {
+            "helditem" => TextInfo::HeldItem {
                 name: ability_name,
                 text: ability_text,
             },
+            "vstarpower" => TextInfo::VstarPower(Box::new(TextInfo::Ability {
+                name: ability_name,
+                text: ability_text,
+            })),
             _ => Err(anyhow!("Unknown ability type: {}", ability_type))?,
         })
     }
 
+    /// Makes an attack, telling GX attacks apart by their name and VSTAR Powers by the
+    /// reminder in their text for cards that don't label them
+    fn make_attack(cost: EnergyCost, name: String, damage: Option<Damage>, text: String) -> Self {
+        if name.ends_with("-GX") {
+            TextInfo::GxAttack {
+                cost,
+                name,
+                damage,
+                text,
+            }
+        } else if text.ends_with(TextInfo::VSTAR_POWER_REMINDER) {
+            TextInfo::VstarPower(Box::new(TextInfo::Attack {
+                cost,
+                name,
+                damage,
+                text,
+            }))
+        } else {
+            TextInfo::Attack {
+                cost,
+                name,
+                damage,
+                text,
+            }
+        }
+    }
+
+    /// The cost, name, damage and text of any kind of attack
+    fn get_attack(&self) -> Option<(&EnergyCost, &str, &Option<Damage>, &str)> {
+        match self {
+            TextInfo::Attack {
+                cost,
+                name,
+                damage,
+                text,
+            }
+            | TextInfo::GxAttack {
+                cost,
+                name,
+                damage,
+                text,
+            } => Some((cost, name, damage, text)),
+            TextInfo::VstarPower(power) => power.get_attack(),
+            _ => None,
+        }
+    }
+
     fn get_ability(&self) -> Result<(AbilityType, AbilityName, AbilityText)> {
         match self {
-            TextInfo::Attack { .. } | TextInfo::Rule { .. } => {
+            TextInfo::Attack { .. } | TextInfo::GxAttack { .. } | TextInfo::Rule { .. } => {
                 bail!("Attempt to get the ability from an attack or rule")
             }
+            TextInfo::VstarPower(power) => power.get_ability(),
             TextInfo::PokeBody { name, text }
             | TextInfo::HeldItem { name, text }
             | TextInfo::AncientTrait { name, text }
@@ -1223,12 +1527,70 @@ impl TextInfo {
             TextInfo::PokemonPower { .. } => TextInfo::POKEMON_POWER.to_string(),
             TextInfo::AncientTrait { .. } => TextInfo::ANCIENT_TRAIT.to_string(),
             TextInfo::HeldItem { .. } => TextInfo::HELD_ITEM.to_string(),
-            TextInfo::Attack { .. } | TextInfo::Rule { .. } => {
+            TextInfo::VstarPower(power) => power.get_ability_type()?,
+            TextInfo::Attack { .. } | TextInfo::GxAttack { .. } | TextInfo::Rule { .. } => {
                 bail!("Tried to access ability type of an attack or rule")
             }
         })
     }
 
+    /// The rich text of the same part of `element` that `parse` reads the text from
+    fn rich_text(element: ElementRef) -> Result<RichText> {
+        let rich_text = RichText::parse(element)?;
+        let discriminator = element.children().next().and_then(ElementRef::wrap);
+        Ok(match discriminator {
+            Some(discriminator)
+                if extract_text(discriminator) == TextInfo::VSTAR_POWER
+                    && discriminator.value().name() != "a" =>
+            {
+                rich_text.after_breaks(2)
+            }
+            Some(discriminator) if ["a", "abbr"].contains(&discriminator.value().name()) => {
+                rich_text.after_breaks(1)
+            }
+            _ => rich_text,
+        })
+    }
+
+    fn set_text(&mut self, new_text: String) {
+        match self {
+            TextInfo::Attack { text, .. }
+            | TextInfo::GxAttack { text, .. }
+            | TextInfo::Ability { text, .. }
+            | TextInfo::PokeBody { text, .. }
+            | TextInfo::PokePower { text, .. }
+            | TextInfo::PokemonPower { text, .. }
+            | TextInfo::AncientTrait { text, .. }
+            | TextInfo::HeldItem { text, .. } => *text = new_text,
+            TextInfo::Rule { rule } => *rule = new_text,
+            TextInfo::VstarPower(power) => power.set_text(new_text),
+        }
+    }
+
+    /// Parses what follows the first break of `element` as its own text info, for VSTAR Powers
+    /// whose label sits on a line of its own above the ability or attack
+    fn parse_after_break(element: ElementRef) -> Result<Self> {
+        let inner = element
+            .children()
+            .skip_while(is_not_break)
+            .skip(1)
+            .map(|node_ref| match node_ref.value() {
+                Node::Element(_) => ElementRef::wrap(node_ref)
+                    .map(|element| element.html())
+                    .unwrap_or_default(),
+                Node::Text(text) => html_escape::encode_text(text.deref()).to_string(),
+                _ => String::new(),
+            })
+            .collect::<String>();
+        let fragment = Html::parse_fragment(&format!("<p>{}</p>", inner.trim_start()));
+        let selector = Selector::parse("p").unwrap();
+        let paragraph = fragment
+            .select(&selector)
+            .next()
+            .ok_or(anyhow!("Nothing follows the break in: {}", element.html()))?;
+        TextInfo::parse(paragraph)
+    }
+
     fn get_text(element: ElementRef) -> Result<String, Error> {
         Ok(clean_text(
             element
@@ -1259,7 +1621,7 @@ impl TextInfo {
         .to_string())
     }
 
-    fn get_cost(element: ElementRef) -> Result<(Option<ElementRef>, Vec<EnergyColor>), Error> {
+    fn get_cost(element: ElementRef) -> Result<(Option<ElementRef>, EnergyCost), Error> {
         let energy_and_br_selector =
             Selector::parse("abbr.ptcg-font.ptcg-symbol-name, br, abbr[title=\"No Energy Cost\"]")
                 .unwrap();
@@ -1290,13 +1652,13 @@ impl TextInfo {
             })
             .collect::<Result<Vec<EnergyColor>>>()
             .context("Failed to extract cost of attack")?;
-        Ok((last_energy, cost))
+        Ok((last_energy, EnergyCost::new(cost)))
     }
 
     fn get_name_and_damage(
         html: String,
         last_energy: Option<ElementRef>,
-    ) -> Result<(String, Option<String>), Error> {
+    ) -> Result<(String, Option<Damage>), Error> {
         let name_and_damage = Self::get_string_til_break(
             last_energy.ok_or(anyhow!("Failed to extract name from: {}", html))?,
         )
@@ -1312,7 +1674,9 @@ impl TextInfo {
             .as_str()
             .trim()
             .to_string();
-        let damage = captures.name("damage").map(|dmg| dmg.as_str().to_string());
+        let damage = captures
+            .name("damage")
+            .map(|dmg| Damage::from(dmg.as_str()));
         Ok((name, damage))
     }
 }
@@ -1367,26 +1731,39 @@ impl PkmnParse for TextInfo {
         Ok({
             if let Some(discriminator) = discriminator_opt {
                 let local_name = &discriminator.value().name.local;
-                if local_name == &CssLocalName::from("a").0 {
+                if extract_text(discriminator) == TextInfo::VSTAR_POWER
+                    && local_name != &CssLocalName::from("a").0
+                {
+                    let power =
+                        Self::parse_after_break(element).context("Failed to parse VSTAR Power")?;
+                    match power {
+                        TextInfo::VstarPower(_) => power,
+                        power => TextInfo::VstarPower(Box::new(power)),
+                    }
+                } else if local_name == &CssLocalName::from("a").0 {
                     let ability_type = discriminator.inner_html();
                     let ability_name = Self::get_string_til_break(discriminator)
                         .context("Failed to extract ability name")?;
                     let ability_text =
                         Self::get_text(element).context("Failed to extract ability text")?;
-                    TextInfo::make_ability(ability_type.as_str(), ability_name, ability_text)
-                        .context("Failed to make ability")?
+                    let ability =
+                        TextInfo::make_ability(ability_type.as_str(), ability_name, ability_text)
+                            .context("Failed to make ability")?;
+                    match ability {
+                        TextInfo::Ability { ref text, .. }
+                            if text.ends_with(TextInfo::VSTAR_POWER_REMINDER) =>
+                        {
+                            TextInfo::VstarPower(Box::new(ability))
+                        }
+                        ability => ability,
+                    }
                 } else if local_name == &CssLocalName::from("abbr").0 {
                     let (last_energy, cost) =
                         Self::get_cost(element).context("Failed to get cost and last_energy")?;
                     let (name, damage) = Self::get_name_and_damage(html, last_energy)
                         .context("Failed to get name and damage")?;
                     let text = Self::get_text(element).context("Failed to get attack text")?;
-                    TextInfo::Attack {
-                        cost,
-                        name,
-                        damage,
-                        text,
-                    }
+                    TextInfo::make_attack(cost, name, damage, text)
                 } else {
                     TextInfo::Rule {
                         rule: extract_text(element),
@@ -1403,8 +1780,12 @@ impl PkmnParse for TextInfo {
 
 #[derive(Eq, PartialEq, Debug)]
 enum Rule {
-    RuleBox { purpose: String, text: String },
-    NonRuleBox { purpose: String, text: String },
+    /// A rule pkmncards links to its rule box page
+    Boxed { purpose: String, text: String },
+    /// Any other rule pkmncards lists with its purpose
+    Unboxed { purpose: String, text: String },
+    /// The bench protection every Tera Pokémon ex has
+    Tera { text: String },
 }
 
 impl PkmnParse for Rule {
@@ -1429,13 +1810,15 @@ impl PkmnParse for Rule {
         let rule_box = select_opt_element(element, rule_box_selector);
 
         let rule_text: String = direct_text_skip_past(element, ":");
-        if rule_box.is_some() {
-            Ok(Rule::RuleBox {
+        if purpose.trim_end_matches(':').eq_ignore_ascii_case("Tera") {
+            Ok(Rule::Tera { text: rule_text })
+        } else if rule_box.is_some() {
+            Ok(Rule::Boxed {
                 purpose,
                 text: rule_text,
             })
         } else {
-            Ok(Rule::NonRuleBox {
+            Ok(Rule::Unboxed {
                 purpose,
                 text: rule_text,
             })
@@ -1453,6 +1836,8 @@ enum Mark {
     E,
     F,
     G,
+    #[strum(default)]
+    Unknown(String),
 }
 
 #[derive(Eq, PartialEq, Debug)]
@@ -1515,25 +1900,15 @@ impl PkmnParse for PtcgFormat {
     }
 }
 
-#[derive(Eq, PartialEq, Debug, EnumString, Display)]
-enum EnergyColor {
-    Grass,
-    Fire,
-    Water,
-    Lightning,
-    Psychic,
-    Fighting,
-    Darkness,
-    Metal,
-    Fairy,
-    Colorless,
-}
-
 //noinspection DuplicatedCode
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::pkmn_data::http::CachePolicy;
+    use crate::pkmn_data::mock_site::MockSite;
+    use crate::pkmn_data::{HttpOptions, FIXTURES};
     use scraper::Html;
+    use std::path::Path;
     use time::Month;
 
     #[test]
@@ -1662,14 +2037,15 @@ Flip 2 coins. For each heads, discard an Energy from your opponent's Active Pok
                         "Dragonite-GX".into(),
                         "Dragonite ex".into(),
                     ],
+                    more_at: None,
                 }),
                 is: HashSet::default(),
             },
             all_text_info: AllTextInfo {
                 text_infos: vec![TextInfo::Attack {
-                    cost: vec![EnergyColor::Water, EnergyColor::Lightning],
+                    cost: EnergyCost::new(vec![EnergyColor::Water, EnergyColor::Lightning]),
                     name: "Twister".to_string(),
-                    damage: Some("30".to_string()),
+                    damage: Some(Damage::from("30")),
                     text: "Flip 2 coins. For each heads, discard an Energy from your opponent's Active Pokémon. If both of them are tails, this attack does nothing.".to_string(),
                 }],
             },
@@ -1795,6 +2171,7 @@ Flip 2 coins. For each heads, discard an Energy from your opponent's Active Pok
                     "Dragonite-GX".to_string(),
                     "Dragonite ex".to_string(),
                 ],
+                more_at: None,
             }),
             is: HashSet::default(),
         };
@@ -1822,6 +2199,27 @@ Flip 2 coins. For each heads, discard an Energy from your opponent's Active Pok
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn parse_type_evolves_is_unknown_vocabulary() {
+        let fragment = Html::parse_fragment(
+            r#"<div class="type-evolves-is"><span class="type" title="Type"><a href="https://pkmncards.com/type/pokemon/">Pokémon</a></span> <span class="pokemons">(<span class="pokemon" title="Pokémon"><a href="https://pkmncards.com/pokemon/koraidon/">Koraidon</a></span>)</span> › <span class="stage" title="Stage of Evolution"><a href="https://pkmncards.com/stage/stage-3/">Stage 3</a></span> › <span class="is" title="Is"><a href="https://pkmncards.com/is/ancient/">Ancient</a></span></div>"#,
+        );
+        let selector = Selector::parse("div").unwrap();
+        let actual = TypeEvolvesIs::parse(fragment.select(&selector).next().unwrap()).unwrap();
+        let expected = TypeEvolvesIs {
+            pkmn_type: PkmnSuperType::Pokemon,
+            pkmn_subtype: None,
+            pkmn_subsubtype: None,
+            all_pokemon: vec!["Koraidon".to_string()],
+            stage: Some(Stage::Unknown("Stage 3".to_string())),
+            evolves: None,
+            is: HashSet::from([PtcgTag::Unknown("ancient".to_string())]),
+        };
+
+        assert_eq!(actual, expected);
+        assert_eq!(actual.stage.unwrap().to_string(), "Stage 3");
+    }
+
     #[test]
     fn parse_type_evolves_is_level_x_pokemon() {
         let fragment = Html::parse_fragment(
@@ -1838,6 +2236,7 @@ Flip 2 coins. For each heads, discard an Energy from your opponent's Active Pok
             evolves: Some(Evolves {
                 from: vec!["Salamence".to_string()],
                 to: vec![],
+                more_at: None,
             }),
             is: HashSet::default(),
         };
@@ -1858,15 +2257,15 @@ Put up to 12 damage counters on this Pokémon. This attack does 20 damage for ea
         let expected = AllTextInfo {
             text_infos: vec![
                 TextInfo::Attack {
-                    cost: vec![EnergyColor::Fighting],
+                    cost: EnergyCost::new(vec![EnergyColor::Fighting]),
                     name: "Angry Grudge".to_string(),
-                    damage: Some("20×".to_string()),
+                    damage: Some(Damage::from("20×")),
                     text: "Put up to 12 damage counters on this Pokémon. This attack does 20 damage for each damage counter you placed in this way.".to_string(),
                 },
                 TextInfo::Attack {
-                    cost: vec![EnergyColor::Fighting, EnergyColor::Colorless],
+                    cost: EnergyCost::new(vec![EnergyColor::Fighting, EnergyColor::Colorless]),
                     name: "Seismic Toss".to_string(),
-                    damage: Some("150".to_string()),
+                    damage: Some(Damage::from("150")),
                     text: "".to_string(),
                 },
             ],
@@ -1889,15 +2288,15 @@ This attack does 20 damage for each Pokémon in your discard pile that has the U
         let expected = AllTextInfo {
             text_infos: vec![
                 TextInfo::Attack {
-                    cost: vec![EnergyColor::Colorless],
+                    cost: EnergyCost::new(vec![EnergyColor::Colorless]),
                     name: "Spin Turn".to_string(),
-                    damage: Some("10".to_string()),
+                    damage: Some(Damage::from("10")),
                     text: "Switch this Pokémon with 1 of your Benched Pokémon.".to_string(),
                 },
                 TextInfo::Attack {
-                    cost: vec![EnergyColor::Darkness],
+                    cost: EnergyCost::new(vec![EnergyColor::Darkness]),
                     name: "United Wings".to_string(),
-                    damage: Some("20×".to_string()),
+                    damage: Some(Damage::from("20×")),
                     text: "This attack does 20 damage for each Pokémon in your discard pile that has the United Wings attack.".to_string(),
                 },
             ],
@@ -2017,11 +2416,106 @@ This attack does 20 damage for each Pokémon in your discard pile that has the U
                 "Tinkaton".to_string(),
                 "Riemann".to_string(),
             ],
+            more_at: None,
         };
 
         assert_eq!(parsed, expected);
     }
 
+    #[test]
+    fn parse_truncated_evolves() {
+        let fragment = Html::parse_fragment(
+            r#"<div class="type-evolves-is"><span class="pokemons">(<span class="pokemon" title="Pokémon"><a href="https://pkmncards.com/pokemon/scyther/">Scyther</a></span>)</span> : <span class="evolves">Evolves into <a href="https://pkmncards.com/name/scizor/" title="Name">Scizor</a>, <a href="https://pkmncards.com/name/scizor-gx/" title="Name">Scizor-<em>GX</em></a>, […] <a href="https://pkmncards.com/name/kleavor/" title="Name">Kleavor</a>, or <a href="https://pkmncards.com/name/scizor-ex-%e2%86%93/" title="Name">Scizor ex</a></span></div>"#,
+        );
+        let evolve_selector = Selector::parse("span.evolves").unwrap();
+
+        let parsed = Evolves::parse(fragment.select(&evolve_selector).next().unwrap()).unwrap();
+        let expected = Evolves {
+            from: vec![],
+            to: vec![
+                "Scizor".to_string(),
+                "Scizor-GX".to_string(),
+                "Kleavor".to_string(),
+                "Scizor ex".to_string(),
+            ],
+            more_at: Some("https://pkmncards.com/?s=evolves-from%3Ascyther".to_string()),
+        };
+
+        assert_eq!(parsed, expected);
+    }
+
+    /// A search result the evolves-to pages list, `name` evolving from `from`
+    fn evolves_result(name: &str, from: &str) -> String {
+        format!(
+            r#"<article class="type-pkmn_card"><div class="name-hp-color"><span class="name">{}</span></div><span class="evolves">Evolves from <a href="https://pkmncards.com/name/{}/">{}</a></span></article>"#,
+            name,
+            from.to_lowercase(),
+            from
+        )
+    }
+
+    #[tokio::test]
+    async fn fetch_truncated_evolves_to() {
+        let site = MockSite::start().await;
+        let page = std::fs::read_to_string(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join(FIXTURES)
+                .join("darkrai-dp-black-star-promos-dp24.html"),
+        )
+        .unwrap()
+        .replace(
+            r#"Darkrai LV.X</a></span>"#,
+            &format!(
+                r#"Darkrai LV.X</a>, <a href="{}">[…]</a> <a href="https://pkmncards.com/name/darkrai-ex/" title="Name">Darkrai ex</a></span>"#,
+                site.url("/evolves/")
+            ),
+        );
+        site.page("/darkrai/", &page);
+        site.page(
+            "/evolves/",
+            &format!(
+                r#"<html><body><main>{}{}{}<a class="next page-numbers" href="{}">Next</a></main></body></html>"#,
+                evolves_result("Darkrai LV.X", "Darkrai"),
+                evolves_result("Darkrai BREAK", "Darkrai"),
+                evolves_result("Cresselia LV.X", "Cresselia"),
+                site.url("/evolves/page/2/")
+            ),
+        );
+        site.page(
+            "/evolves/page/2/",
+            &format!(
+                "<html><body><main>{}</main></body></html>",
+                evolves_result("Darkrai VMAX", "Darkrai")
+            ),
+        );
+
+        let cache = tempfile::tempdir().unwrap();
+        let fetcher = CardFetcher {
+            url: site.url("/darkrai/"),
+            client: HttpOptions {
+                cache_dir: cache.path().to_path_buf(),
+                cache_policy: CachePolicy::NoStore,
+                ..HttpOptions::default()
+            }
+            .client()
+            .unwrap(),
+            text_format: TextFormat::Plain,
+            drift: Default::default(),
+        };
+        let (card, _) = fetcher.fetch().await.unwrap();
+        assert_eq!(
+            card.evolves_to.unwrap(),
+            [
+                "Darkrai LV.X",
+                "Darkrai ex",
+                "Darkrai BREAK",
+                "Darkrai VMAX"
+            ]
+        );
+        assert_eq!(card.enriched, ["evolvesTo"]);
+        assert_eq!(site.hits("/evolves/page/2/"), 1);
+    }
+
     #[test]
     fn parse_ptcg_tag() {
         let fragment = Html::parse_fragment(
@@ -2038,7 +2532,10 @@ This attack does 20 damage for each Pokémon in your discard pile that has the U
                     .value()
                     .attr("value")
                     .ok_or(anyhow!("The checkbox did not contain a value"))?;
-                PtcgTag::from_str(val).map_err(Error::from)
+                match PtcgTag::from_str(val)? {
+                    PtcgTag::Unknown(tag) => Err(anyhow!("Unknown ptcg tag: {}", tag)),
+                    tag => Ok(tag),
+                }
             })
             .collect::<Result<Vec<PtcgTag>>>()
             .expect("An error occurred while parsing a ptcg tag");
@@ -2146,7 +2643,7 @@ As often as you like during your turn <em>(before your attack)</em>, you may tur
        Flip a coin. If heads, the Defending Pokémon is now Poisoned.</p>"#,
         );
         let expected = TextInfo::Attack {
-            cost: vec![],
+            cost: EnergyCost::new(vec![]),
             name: "Poison Breath".to_string(),
             damage: None,
             text: "Flip a coin. If heads, the Defending Pokémon is now Poisoned.".to_string(),
@@ -2162,7 +2659,7 @@ As often as you like during your turn <em>(before your attack)</em>, you may tur
 Put 1 damage counter on each of your opponent's Pokémon.</p>"#,
         );
         let expected = TextInfo::Attack {
-            cost: vec![EnergyColor::Grass],
+            cost: EnergyCost::new(vec![EnergyColor::Grass]),
             name: "Sparkle Motion".to_string(),
             damage: None,
             text: "Put 1 damage counter on each of your opponent's Pokémon.".to_string(),
@@ -2178,16 +2675,110 @@ Put 1 damage counter on each of your opponent's Pokémon.</p>"#,
 If this Pokémon has at least 1 extra Energy attached to it <em>(in addition to this attack's cost)</em>, heal all damage from all of your Pokémon. <em>(You can't use more than 1 <em>GX</em> attack in a game.)</em></p>"#,
         );
 
-        let expected = TextInfo::Attack {
-            cost: vec![EnergyColor::Psychic, EnergyColor::Psychic, EnergyColor::Colorless],
+        let expected = TextInfo::GxAttack {
+            cost: EnergyCost::new(vec![EnergyColor::Psychic, EnergyColor::Psychic, EnergyColor::Colorless]),
             name: "Miraculous Duo-GX".to_string(),
-            damage: Some("200".to_string()),
+            damage: Some(Damage::from("200")),
             text: "If this Pokémon has at least 1 extra Energy attached to it (in addition to this attack's cost), heal all damage from all of your Pokémon. (You can't use more than 1 GX attack in a game.)".to_string(),
         };
 
         parse_text_info(expected, fragment);
     }
 
+    #[test]
+    fn parse_gx_attack() {
+        let fragment = Html::parse_fragment(
+            r#"<p><abbr title="Lightning" class="ptcg-font ptcg-symbol-name"><span class="vh">{</span>L<span class="vh">}</span></abbr><abbr title="Lightning" class="ptcg-font ptcg-symbol-name"><span class="vh">{</span>L<span class="vh">}</span></abbr><abbr title="Colorless" class="ptcg-font ptcg-symbol-name"><span class="vh">{</span>C<span class="vh">}</span></abbr> → <span>Tapu Thunder-<em>GX</em></span> : 50×<br>
+This attack does 50 damage times the amount of Energy attached to all of your opponent's Pokémon. <em>(You can't use more than 1 <em>GX</em> attack in a game.)</em></p>"#,
+        );
+        let expected = TextInfo::GxAttack {
+            cost: EnergyCost::new(vec![EnergyColor::Lightning, EnergyColor::Lightning, EnergyColor::Colorless]),
+            name: "Tapu Thunder-GX".to_string(),
+            damage: Some(Damage::from("50×")),
+            text: "This attack does 50 damage times the amount of Energy attached to all of your opponent's Pokémon. (You can't use more than 1 GX attack in a game.)".to_string(),
+        };
+
+        parse_text_info(expected, fragment);
+    }
+
+    #[test]
+    fn parse_vstar_power_ability() {
+        let fragment = Html::parse_fragment(
+            r#"<p><em>VSTAR Power</em><br>
+<a href="https://pkmncards.com/has/ability/">Ability</a> ⇢ Starbirth<br>
+During your turn, you may search your deck for up to 2 cards and put them into your hand. Then, shuffle your deck. <em>(You can't use more than 1 <em>VSTAR</em> Power in a game.)</em></p>"#,
+        );
+        let expected = TextInfo::VstarPower(Box::new(TextInfo::Ability {
+            name: "Starbirth".to_string(),
+            text: "During your turn, you may search your deck for up to 2 cards and put them into your hand. Then, shuffle your deck. (You can't use more than 1 VSTAR Power in a game.)".to_string(),
+        }));
+
+        parse_text_info(expected, fragment);
+    }
+
+    #[test]
+    fn parse_vstar_power_attack() {
+        let fragment = Html::parse_fragment(
+            r#"<p><em>VSTAR Power</em><br>
+<abbr title="Metal" class="ptcg-font ptcg-symbol-name"><span class="vh">{</span>M<span class="vh">}</span></abbr><abbr title="Metal" class="ptcg-font ptcg-symbol-name"><span class="vh">{</span>M<span class="vh">}</span></abbr><abbr title="Colorless" class="ptcg-font ptcg-symbol-name"><span class="vh">{</span>C<span class="vh">}</span></abbr> → <span>Star Chronos</span> : 220<br>
+Take another turn after this one. <em>(Skip Pokémon Checkup.)</em> <em>(You can't use more than 1 <em>VSTAR</em> Power in a game.)</em></p>"#,
+        );
+        let expected = TextInfo::VstarPower(Box::new(TextInfo::Attack {
+            cost: EnergyCost::new(vec![EnergyColor::Metal, EnergyColor::Metal, EnergyColor::Colorless]),
+            name: "Star Chronos".to_string(),
+            damage: Some(Damage::from("220")),
+            text: "Take another turn after this one. (Skip Pokémon Checkup.) (You can't use more than 1 VSTAR Power in a game.)".to_string(),
+        }));
+
+        parse_text_info(expected, fragment);
+    }
+
+    #[test]
+    fn parse_unlabelled_vstar_power() {
+        let fragment = Html::parse_fragment(
+            r#"<p><a href="https://pkmncards.com/has/ability/">Ability</a> ⇢ Star Alchemy<br>
+During your turn, you may search your deck for a card and put it into your hand. Then, shuffle your deck. <em>(You can't use more than 1 <em>VSTAR</em> Power in a game.)</em></p>"#,
+        );
+        let expected = TextInfo::VstarPower(Box::new(TextInfo::Ability {
+            name: "Star Alchemy".to_string(),
+            text: "During your turn, you may search your deck for a card and put it into your hand. Then, shuffle your deck. (You can't use more than 1 VSTAR Power in a game.)".to_string(),
+        }));
+
+        parse_text_info(expected, fragment);
+    }
+
+    #[test]
+    fn parse_poke_body_spelling() {
+        let fragment = Html::parse_fragment(
+            r#"<p><a href="https://pkmncards.com/has/poke-body/">Poké-Body</a> ⇢ Exoskeleton<br>
+Any damage done to Donphan by attacks is reduced by 20 <em>(after applying Weakness and Resistance)</em>.</p>"#,
+        );
+
+        let expected = TextInfo::PokeBody {
+            name: "Exoskeleton".to_string(),
+            text: "Any damage done to Donphan by attacks is reduced by 20 (after applying Weakness and Resistance).".to_string(),
+        };
+
+        parse_text_info(expected, fragment);
+    }
+
+    #[test]
+    fn parse_tera_rule() {
+        let fragment = Html::parse_fragment(
+            r#"<div class="rule tera">· <em>Tera:</em> As long as this Pokémon is on your Bench, prevent all damage done to this Pokémon by attacks <em>(both yours and your opponent's)</em>.</div>"#,
+        );
+        let selector = Selector::parse("div").unwrap();
+
+        let rule = Rule::Tera {
+            text: "As long as this Pokémon is on your Bench, prevent all damage done to this Pokémon by attacks (both yours and your opponent's).".to_string(),
+        };
+
+        assert_eq!(
+            rule,
+            Rule::parse(fragment.select(&selector).next().unwrap()).unwrap()
+        );
+    }
+
     #[test]
     fn parse_rule() {
         let fragment = Html::parse_fragment(
@@ -2195,7 +2786,7 @@ If this Pokémon has at least 1 extra Energy attached to it <em>(in addition to
         );
         let selector = Selector::parse("div").unwrap();
 
-        let rule = Rule::RuleBox {
+        let rule = Rule::Boxed {
             purpose: "TAG TEAM".to_string(),
             text: "When your TAG TEAM is Knocked Out, your opponent takes 3 Prize cards."
                 .to_string(),
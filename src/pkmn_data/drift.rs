@@ -0,0 +1,73 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+
+/// How often each tag, stage, subtype, mark or color the parser doesn't know came up, by kind.
+/// New values usually mean a new mechanic the enums in `card` should learn about.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq)]
+#[serde(transparent)]
+pub struct VocabularyDrift {
+    kinds: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+impl VocabularyDrift {
+    pub async fn load(path: impl AsRef<Path>) -> Result<VocabularyDrift> {
+        let file = File::open(path).await?;
+        let mut reader = BufReader::new(file);
+        let mut buffer: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut buffer).await?;
+        Ok(serde_json::from_slice(&buffer)?)
+    }
+
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let file = File::create(path).await?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(&serde_json::to_vec_pretty(self)?).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+
+    pub(super) fn record(&mut self, kind: &str, value: &str) {
+        *self
+            .kinds
+            .entry(kind.to_string())
+            .or_default()
+            .entry(value.to_string())
+            .or_default() += 1;
+    }
+
+    /// Adds the counts of `other` to these
+    pub fn merge(&mut self, other: VocabularyDrift) {
+        for (kind, values) in other.kinds {
+            let counts = self.kinds.entry(kind).or_default();
+            for (value, count) in values {
+                *counts.entry(value).or_default() += count;
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_empty()
+    }
+
+    /// The kinds with their unknown values, most common first
+    pub fn summary(&self) -> String {
+        self.kinds
+            .iter()
+            .map(|(kind, values)| {
+                let mut values = values.iter().collect::<Vec<_>>();
+                values.sort_by(|(_, left), (_, right)| right.cmp(left));
+                let values = values
+                    .iter()
+                    .map(|(value, count)| format!("{} ({})", value, count))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{}: {}", kind, values)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
@@ -0,0 +1,81 @@
+use super::card::parse_card_page;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+
+/// The corpus of card pages, each next to a golden `.json` of the card it parses to. Pages
+/// keep the whole card entry but drop the site around it, like scripts and comments.
+pub const FIXTURES: &str = "fixtures/pkmncards/cards";
+
+/// The card pages in `dir`, in name order
+fn fixture_pages(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut pages = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read the fixtures in {}", dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .filter(|path| {
+            path.as_ref().map_or(true, |path| {
+                path.extension().is_some_and(|ext| ext == "html")
+            })
+        })
+        .collect::<Result<Vec<PathBuf>>>()?;
+    pages.sort();
+    Ok(pages)
+}
+
+/// Rewrites the golden of every page in `dir` with what the parser makes of it now. Meant for
+/// after intentional parser changes, the diff of the goldens shows what the change did.
+pub async fn bless(dir: &Path) -> Result<usize> {
+    let pages = fixture_pages(dir)?;
+    for page in &pages {
+        let file = File::open(page).await?;
+        let mut reader = BufReader::new(file);
+        let mut buffer = String::new();
+        reader.read_to_string(&mut buffer).await?;
+        let card = parse_card_page(&buffer)
+            .with_context(|| format!("Failed to parse {}", page.display()))?;
+
+        let mut output = serde_json::to_vec_pretty(&card)?;
+        output.push(b'\n');
+        let file = File::create(page.with_extension("json")).await?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(&output).await?;
+        writer.flush().await?;
+    }
+    Ok(pages.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ptcgio_data::Card;
+
+    #[test]
+    fn fixtures_match_goldens() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(FIXTURES);
+        let pages = fixture_pages(&dir).unwrap();
+        assert!(!pages.is_empty(), "No fixtures in {}", dir.display());
+
+        let mismatches = pages
+            .iter()
+            .filter_map(|page| {
+                let html = std::fs::read_to_string(page).unwrap();
+                let golden = std::fs::read_to_string(page.with_extension("json"))
+                    .map_err(anyhow::Error::from)
+                    .and_then(|golden| Ok(serde_json::from_str::<Card>(&golden)?));
+                let outcome = match (parse_card_page(&html), golden) {
+                    (Ok(card), Ok(golden)) if card == golden => return None,
+                    (Ok(card), Ok(golden)) => format!("parsed\n{:#?}\ngolden\n{:#?}", card, golden),
+                    (Err(err), _) => format!("failed to parse: {:?}", err),
+                    (_, Err(err)) => format!("no golden: {:?}", err),
+                };
+                Some(format!("{}: {}", page.display(), outcome))
+            })
+            .collect::<Vec<String>>();
+        assert!(
+            mismatches.is_empty(),
+            "Run `bless-fixtures` if these changes are intended:\n{}",
+            mismatches.join("\n\n")
+        );
+    }
+}
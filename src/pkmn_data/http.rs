@@ -0,0 +1,132 @@
+use anyhow::Result;
+use http_cache_reqwest::{CACacheManager, Cache, CacheMode, HttpCache, HttpCacheOptions};
+use reqwest::header::{HeaderValue, CACHE_CONTROL};
+use reqwest::{Request, Response};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, Next};
+use reqwest_retry::policies::{ExponentialBackoff, ExponentialBackoffBuilder};
+use reqwest_retry::RetryTransientMiddleware;
+use std::path::PathBuf;
+use std::time::Duration;
+use task_local_extensions::Extensions;
+
+/// How pages are looked up in the HTTP cache before going to pkmncards
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default, clap::ValueEnum, strum::Display)]
+#[strum(serialize_all = "kebab-case")]
+pub enum CachePolicy {
+    /// Fresh cached pages are used as is and stale ones revalidated
+    #[default]
+    Default,
+    /// Cached pages are used however stale, only missing ones are fetched
+    ForceCache,
+    /// The cache is neither read nor written
+    NoStore,
+    /// Every cached page is revalidated with pkmncards and the cache updated with the answer
+    Refresh,
+    /// Only cached pages are used, pages that aren't cached fail without going to the network
+    WarmOnly,
+}
+
+impl From<CachePolicy> for CacheMode {
+    fn from(policy: CachePolicy) -> Self {
+        match policy {
+            CachePolicy::Default => CacheMode::Default,
+            CachePolicy::ForceCache => CacheMode::ForceCache,
+            CachePolicy::NoStore => CacheMode::NoStore,
+            CachePolicy::Refresh => CacheMode::NoCache,
+            CachePolicy::WarmOnly => CacheMode::OnlyIfCached,
+        }
+    }
+}
+
+/// How pages are fetched from pkmncards and cached
+#[derive(clap::Args, Debug, Clone, Eq, PartialEq)]
+pub struct HttpOptions {
+    /// The directory fetched pages are cached in
+    #[arg(long, global = true, default_value = "http-cacache")]
+    pub cache_dir: PathBuf,
+    /// How the cache is used, `warm-only` never goes to the network
+    #[arg(long, global = true, value_enum, default_value_t)]
+    pub cache_policy: CachePolicy,
+    /// Seconds cached pages stay fresh, instead of what pkmncards' headers say
+    #[arg(long, global = true)]
+    pub max_age: Option<u64>,
+    /// The User-Agent header sent with every request instead of none
+    #[arg(long, global = true)]
+    pub user_agent: Option<String>,
+    /// Seconds a request may take before it fails
+    #[arg(long, global = true)]
+    pub timeout: Option<u64>,
+    /// How often a request failing with a transient error is tried again
+    #[arg(long, global = true, default_value_t = 2)]
+    pub retries: u32,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            cache_dir: PathBuf::from("http-cacache"),
+            cache_policy: CachePolicy::default(),
+            max_age: None,
+            user_agent: None,
+            timeout: None,
+            retries: 2,
+        }
+    }
+}
+
+impl HttpOptions {
+    pub(super) fn client(&self) -> Result<ClientWithMiddleware> {
+        self.client_with_backoff(ExponentialBackoff::builder())
+    }
+
+    /// The client with retries spaced out by `backoff`
+    pub(super) fn client_with_backoff(
+        &self,
+        backoff: ExponentialBackoffBuilder,
+    ) -> Result<ClientWithMiddleware> {
+        let mut client = reqwest::Client::builder().pool_max_idle_per_host(0);
+        if let Some(user_agent) = &self.user_agent {
+            client = client.user_agent(user_agent);
+        }
+        if let Some(timeout) = self.timeout {
+            client = client.timeout(Duration::from_secs(timeout));
+        }
+        let mut client = ClientBuilder::new(client.build()?).with(Cache(HttpCache {
+            mode: self.cache_policy.into(),
+            manager: CACacheManager {
+                path: self.cache_dir.clone(),
+            },
+            options: HttpCacheOptions::default(),
+        }));
+        client = client.with(RetryTransientMiddleware::new_with_policy(
+            backoff.build_with_max_retries(self.retries),
+        ));
+        if let Some(max_age) = self.max_age {
+            client = client.with(MaxAge(max_age));
+        }
+        Ok(client.build())
+    }
+}
+
+/// Marks successful responses fresh for the given seconds before the cache sees them
+struct MaxAge(u64);
+
+#[async_trait::async_trait]
+impl Middleware for MaxAge {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let mut response = next.run(req, extensions).await?;
+        if response.status().is_success() {
+            response.headers_mut().insert(
+                CACHE_CONTROL,
+                HeaderValue::from_str(&format!("max-age={}", self.0))
+                    .expect("a number is a valid header value"),
+            );
+        }
+        Ok(response)
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// What goes wrong when a page is requested
+#[derive(Clone, Copy)]
+pub(super) enum Fault {
+    NotFound,
+    /// Answers with a 500 the first `times` requests
+    ServerError {
+        times: usize,
+    },
+    Slow(Duration),
+    /// Cuts the page off halfway
+    Malformed,
+}
+
+#[derive(Default)]
+struct Routes {
+    pages: HashMap<String, String>,
+    faults: HashMap<String, Fault>,
+    hits: HashMap<String, usize>,
+}
+
+/// A stand-in for pkmncards serving pages from memory on a local port
+pub(super) struct MockSite {
+    base: String,
+    routes: Arc<Mutex<Routes>>,
+}
+
+impl MockSite {
+    pub(super) async fn start() -> MockSite {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let routes = Arc::new(Mutex::new(Routes::default()));
+        let server_routes = routes.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, server_routes.clone()));
+            }
+        });
+        MockSite { base, routes }
+    }
+
+    pub(super) fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base, path)
+    }
+
+    pub(super) fn page(&self, path: &str, body: &str) {
+        let mut routes = self.routes.lock().unwrap();
+        routes.pages.insert(path.to_string(), body.to_string());
+    }
+
+    pub(super) fn fault(&self, path: &str, fault: Fault) {
+        let mut routes = self.routes.lock().unwrap();
+        routes.faults.insert(path.to_string(), fault);
+    }
+
+    /// How often `path` has been requested
+    pub(super) fn hits(&self, path: &str) -> usize {
+        let routes = self.routes.lock().unwrap();
+        routes.hits.get(path).copied().unwrap_or(0)
+    }
+}
+
+async fn serve(mut stream: TcpStream, routes: Arc<Mutex<Routes>>) {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return,
+            Ok(read) => request.extend_from_slice(&buffer[..read]),
+        }
+    }
+    let request = String::from_utf8_lossy(&request);
+    let path = request.split_whitespace().nth(1).unwrap_or("/").to_string();
+
+    let (fault, page, hits) = {
+        let mut routes = routes.lock().unwrap();
+        let hits = routes.hits.entry(path.clone()).or_default();
+        *hits += 1;
+        let hits = *hits;
+        (
+            routes.faults.get(&path).copied(),
+            routes.pages.get(&path).cloned(),
+            hits,
+        )
+    };
+    let (status, body) = match (fault, page) {
+        (Some(Fault::NotFound), _) | (_, None) => ("404 Not Found", "Not Found".to_string()),
+        (Some(Fault::ServerError { times }), _) if hits <= times => (
+            "500 Internal Server Error",
+            "Internal Server Error".to_string(),
+        ),
+        (Some(Fault::Slow(delay)), Some(page)) => {
+            tokio::time::sleep(delay).await;
+            ("200 OK", page)
+        }
+        (Some(Fault::Malformed), Some(page)) => {
+            let half = page
+                .char_indices()
+                .map(|(idx, _)| idx)
+                .find(|idx| *idx >= page.len() / 2)
+                .unwrap_or(0);
+            ("200 OK", page[..half].to_string())
+        }
+        (_, Some(page)) => ("200 OK", page),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=UTF-8\r\nContent-Length: {}\r\nCache-Control: max-age=3600\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
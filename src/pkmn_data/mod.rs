@@ -1,26 +1,41 @@
 mod card;
+mod drift;
 mod extractors;
+mod fixtures;
+mod http;
+#[cfg(test)]
+mod mock_site;
+mod rich_text;
 mod series;
 mod set;
 
+pub use drift::VocabularyDrift;
+pub use fixtures::{bless, FIXTURES};
+pub use http::HttpOptions;
+pub use rich_text::TextFormat;
+pub use series::Series;
 pub use set::Set;
 
-use crate::pkmn_data::series::{Series, SeriesFetcher};
-use anyhow::{bail, Result};
-use http_cache_reqwest::{CACacheManager, Cache, CacheMode, HttpCache, HttpCacheOptions};
-use reqwest_middleware::ClientBuilder;
-use reqwest_retry::policies::ExponentialBackoff;
-use reqwest_retry::RetryTransientMiddleware;
+use crate::pkmn_data::series::SeriesFetcher;
+use anyhow::{bail, Context, Result};
+use reqwest_middleware::ClientWithMiddleware;
 use scraper::{Html, Selector};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 
 pub struct DataFetcher {
     url: String,
     store_path: PathBuf,
+    text_format: TextFormat,
+    http: HttpOptions,
+    drift: Arc<Mutex<VocabularyDrift>>,
 }
 
-const SETS_URL: &str = "https://pkmncards.com/sets/";
+/// Where the counts of unknown values are kept in the store
+const DRIFT_REPORT: &str = "vocabulary_drift.json";
+
+pub const SETS_URL: &str = "https://pkmncards.com/sets/";
 
 impl Default for DataFetcher {
     fn default() -> Self {
@@ -33,24 +48,24 @@ impl DataFetcher {
         DataFetcher {
             url: data_start_url.to_string(),
             store_path: store_path.to_path_buf(),
+            text_format: TextFormat::default(),
+            http: HttpOptions::default(),
+            drift: Arc::default(),
         }
     }
 
-    pub async fn fetch(self) -> Result<Data> {
-        let retry_policy = ExponentialBackoff::builder().build_with_max_retries(2);
-        let client = ClientBuilder::new(
-            reqwest::Client::builder()
-                .pool_max_idle_per_host(0)
-                .build()?,
-        )
-        .with(Cache(HttpCache {
-            mode: CacheMode::Default,
-            manager: CACacheManager::default(),
-            options: HttpCacheOptions::default(),
-        }))
-        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-        .build();
+    /// Writes the text of attacks, abilities and rules as `text_format` instead of plain text
+    pub fn with_text_format(mut self, text_format: TextFormat) -> Self {
+        self.text_format = text_format;
+        self
+    }
 
+    pub fn with_http(mut self, http: HttpOptions) -> Self {
+        self.http = http;
+        self
+    }
+
+    async fn series_fetchers(&self, client: &ClientWithMiddleware) -> Result<Vec<SeriesFetcher>> {
         let result = client.get(&self.url).send().await?;
 
         let sets_html = if result.status().is_success() {
@@ -66,29 +81,501 @@ impl DataFetcher {
 
         let series_selector = Selector::parse("h2 > a").unwrap();
 
-        let series_fetchers = set_doc
+        set_doc
             .select(&series_selector)
-            .map(|block| SeriesFetcher::new(block, &client))
-            .collect::<Result<Vec<SeriesFetcher>>>()?;
+            .map(|block| SeriesFetcher::new(block, client, self.text_format, &self.drift))
+            .collect::<Result<Vec<SeriesFetcher>>>()
+    }
+
+    /// Lists the sets pkmncards has from the page of all sets only, without fetching any cards.
+    /// Sets already in the store come with their stored cards.
+    pub async fn list_sets(&self) -> Result<Vec<SetListing>> {
+        let client = self.http.client()?;
+        let mut listings = Vec::new();
+        for series_fetcher in self.series_fetchers(&client).await? {
+            listings.extend(series_fetcher.list_sets(&self.store_path).await?);
+        }
+        Ok(listings)
+    }
+
+    pub async fn fetch(self) -> Result<Data> {
+        let client = self.http.client()?;
+        self.fetch_with(&client).await
+    }
+
+    async fn fetch_with(self, client: &ClientWithMiddleware) -> Result<Data> {
+        let series_fetchers = self.series_fetchers(client).await?;
 
         if !&self.store_path.exists() {
             tokio::fs::create_dir_all(&self.store_path).await?;
         }
 
-        let mut all_series = Vec::new();
-
-        for series_future in series_fetchers
-            .iter()
-            .map(|fetcher| fetcher.fetch(&self.store_path))
-        {
-            all_series.push(series_future.await?);
+        let fetched = async {
+            let mut all_series = Vec::new();
+            for series_future in series_fetchers
+                .iter()
+                .map(|fetcher| fetcher.fetch(&self.store_path))
+            {
+                all_series.push(series_future.await?);
+            }
+            Ok::<_, anyhow::Error>(all_series)
         }
+        .await;
+        // New vocabulary is a likely reason for a failed fetch, so it's reported either way
+        let saved = self.save_drift().await;
+        let all_series = fetched?;
+        saved?;
 
         Ok(Data { all_series })
     }
+
+    /// Adds the unknown values of the cards fetched this time to the report in the store. Cards
+    /// of stored sets aren't parsed again, so their counts are only ever in the report.
+    async fn save_drift(&self) -> Result<()> {
+        let drift = std::mem::take(&mut *self.drift.lock().unwrap());
+        if drift.is_empty() {
+            return Ok(());
+        }
+        eprintln!(
+            "Values pkmncards uses that the parser doesn't know:\n{}",
+            drift.summary()
+        );
+        let path = self.store_path.join(DRIFT_REPORT);
+        let mut report = if path.exists() {
+            VocabularyDrift::load(&path).await?
+        } else {
+            VocabularyDrift::default()
+        };
+        report.merge(drift);
+        report.save(&path).await
+    }
+}
+
+pub struct SetListing {
+    pub name: String,
+    /// The set code pkmncards shows next to the name, like `SSP` for Surging Sparks
+    pub code: Option<String>,
+    pub series: String,
+    pub stored: Option<Set>,
 }
 
 #[derive(Debug)]
 pub struct Data {
     pub all_series: Vec<Series>,
 }
+
+impl Data {
+    /// Loads the sets already in a store without going to pkmncards. Series are named after
+    /// their directories, the snake case of their names, and sets still being fetched are left
+    /// out.
+    pub async fn open(store_path: &Path) -> Result<Data> {
+        let mut all_series = Vec::new();
+        for series_dir in sorted_entries(store_path).await? {
+            if !series_dir.is_dir() {
+                continue;
+            }
+            let mut sets = Vec::new();
+            for set_path in sorted_entries(&series_dir).await? {
+                if set_path.extension().is_some_and(|ext| ext == "json") {
+                    sets.push(Set::load(&set_path).await?);
+                }
+            }
+            let name = series_dir.file_name().unwrap_or_default();
+            all_series.push(Series {
+                name: name.to_string_lossy().to_string(),
+                sets,
+            });
+        }
+        Ok(Data { all_series })
+    }
+}
+
+async fn sorted_entries(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut entries = tokio::fs::read_dir(dir)
+        .await
+        .with_context(|| format!("Failed to read {}", dir.display()))?;
+    let mut paths = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        paths.push(entry.path());
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::http::CachePolicy;
+    use super::mock_site::{Fault, MockSite};
+    use super::*;
+    use reqwest_retry::policies::ExponentialBackoff;
+    use std::time::Duration;
+
+    const SETS: &str = "/sets/";
+    const BRILLIANT_STARS: &str = "/set/brilliant-stars/";
+    const TEAM_UP: &str = "/set/team-up/";
+    const CELESTIAL_STORM: &str = "/set/celestial-storm/";
+    const ARCEUS: &str = "/arceus-vstar-brilliant-stars-123/";
+    const MEWTWO: &str = "/mewtwo-v-union-sword-shield-promos-swsh159/";
+    const PIKACHU: &str = "/pikachu-zekrom-gx-team-up-33/";
+    const JIRACHI: &str = "/jirachi-prism-star-celestial-storm-97/";
+
+    /// Two series of a set or two each, with card pages from the fixtures
+    async fn synthetic_site() -> MockSite {
+        let site = MockSite::start().await;
+        site.page(
+            SETS,
+            &format!(
+                r#"<html><body><main><div class="entry-content">
+<h2><a href="{}">Sword &amp; Shield</a></h2>
+<ul><li><a href="{}">Brilliant Stars (BRS)</a></li></ul>
+<h2><a href="{}">Sun &amp; Moon</a></h2>
+<ul><li><a href="{}">Team Up (TEU)</a></li><li><a href="{}">Celestial Storm (CES)</a></li></ul>
+</div></main></body></html>"#,
+                site.url("/series/sword-shield/"),
+                site.url(BRILLIANT_STARS),
+                site.url("/series/sun-moon/"),
+                site.url(TEAM_UP),
+                site.url(CELESTIAL_STORM),
+            ),
+        );
+        for (set, cards) in [
+            (BRILLIANT_STARS, vec![ARCEUS, MEWTWO]),
+            (TEAM_UP, vec![PIKACHU]),
+            (CELESTIAL_STORM, vec![JIRACHI]),
+        ] {
+            let articles = cards
+                .iter()
+                .map(|card| {
+                    format!(
+                        r#"<article class="type-pkmn_card"><div class="entry-content"><a class="card-image-link" href="{}"><img src="card.jpg"></a></div></article>"#,
+                        site.url(card)
+                    )
+                })
+                .collect::<String>();
+            site.page(
+                set,
+                &format!("<html><body><main>{}</main></body></html>", articles),
+            );
+            for card in cards {
+                let page = Path::new(env!("CARGO_MANIFEST_DIR"))
+                    .join(FIXTURES)
+                    .join(card.trim_matches('/'))
+                    .with_extension("html");
+                site.page(card, &std::fs::read_to_string(page).unwrap());
+            }
+        }
+        site
+    }
+
+    fn options(cache: &Path) -> HttpOptions {
+        HttpOptions {
+            cache_dir: cache.to_path_buf(),
+            ..HttpOptions::default()
+        }
+    }
+
+    /// The client of `options` retrying right away so failing pages don't slow the tests down
+    fn client_of(options: &HttpOptions) -> ClientWithMiddleware {
+        let backoff = ExponentialBackoff::builder()
+            .retry_bounds(Duration::from_millis(1), Duration::from_millis(5));
+        options.client_with_backoff(backoff).unwrap()
+    }
+
+    fn client(cache: &Path) -> ClientWithMiddleware {
+        client_of(&options(cache))
+    }
+
+    async fn fetch(site: &MockSite, client: &ClientWithMiddleware) -> (tempfile::TempDir, Data) {
+        let store = tempfile::tempdir().unwrap();
+        let data = DataFetcher::new(&site.url(SETS), store.path())
+            .fetch_with(client)
+            .await
+            .unwrap();
+        (store, data)
+    }
+
+    /// A set's name with the names of its cards
+    type SetOutline = (String, Vec<String>);
+
+    /// The series with the names of their sets and cards
+    fn structure(data: &Data) -> Vec<(String, Vec<SetOutline>)> {
+        data.all_series
+            .iter()
+            .map(|series| {
+                let sets = series
+                    .sets
+                    .iter()
+                    .map(|set| {
+                        let cards = set.cards.iter().map(|card| card.name.clone()).collect();
+                        (set.name.clone(), cards)
+                    })
+                    .collect();
+                (series.name.clone(), sets)
+            })
+            .collect()
+    }
+
+    fn set_names(data: &Data) -> Vec<String> {
+        structure(data)
+            .into_iter()
+            .flat_map(|(_, sets)| sets.into_iter().map(|(name, _)| name))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn crawl_series_sets_and_cards() {
+        let site = synthetic_site().await;
+        let cache = tempfile::tempdir().unwrap();
+        let (store, data) = fetch(&site, &client(cache.path())).await;
+
+        let owned = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        assert_eq!(
+            structure(&data),
+            vec![
+                (
+                    "Sword & Shield".to_string(),
+                    vec![(
+                        "Brilliant Stars".to_string(),
+                        owned(&["Arceus VSTAR", "Mewtwo V-UNION"])
+                    )]
+                ),
+                (
+                    "Sun & Moon".to_string(),
+                    vec![
+                        ("Team Up".to_string(), owned(&["Pikachu & Zekrom-GX"])),
+                        ("Celestial Storm".to_string(), owned(&["Jirachi ◇"])),
+                    ]
+                ),
+            ]
+        );
+        let celestial_storm = store.path().join("sun_moon").join("celestial_storm.json");
+        let stored = Set::load(&celestial_storm).await.unwrap();
+        assert_eq!(stored.released.as_deref(), Some("2018/08/03"));
+        // The earliest of the cards, the Mewtwo promo ahead of Arceus
+        let brilliant_stars = &data.all_series[0].sets[0];
+        assert_eq!(brilliant_stars.released.as_deref(), Some("2021/11/12"));
+    }
+
+    #[tokio::test]
+    async fn retry_server_errors() {
+        let site = synthetic_site().await;
+        site.fault(PIKACHU, Fault::ServerError { times: 2 });
+        site.fault(CELESTIAL_STORM, Fault::ServerError { times: 3 });
+        let cache = tempfile::tempdir().unwrap();
+        let (_store, data) = fetch(&site, &client(cache.path())).await;
+
+        assert_eq!(site.hits(PIKACHU), 3);
+        assert_eq!(site.hits(CELESTIAL_STORM), 3);
+        assert_eq!(set_names(&data), vec!["Brilliant Stars", "Team Up"]);
+    }
+
+    #[tokio::test]
+    async fn skip_sets_that_fail() {
+        let site = synthetic_site().await;
+        site.fault(TEAM_UP, Fault::NotFound);
+        site.fault(MEWTWO, Fault::Malformed);
+        let cache = tempfile::tempdir().unwrap();
+        let (store, data) = fetch(&site, &client(cache.path())).await;
+
+        assert_eq!(site.hits(TEAM_UP), 1);
+        assert_eq!(set_names(&data), vec!["Celestial Storm"]);
+        assert!(!store
+            .path()
+            .join("sword_shield")
+            .join("brilliant_stars.json")
+            .exists());
+    }
+
+    #[tokio::test]
+    async fn fail_without_the_sets_page() {
+        let site = synthetic_site().await;
+        site.fault(SETS, Fault::ServerError { times: 3 });
+        let cache = tempfile::tempdir().unwrap();
+        let store = tempfile::tempdir().unwrap();
+        let fetched = DataFetcher::new(&site.url(SETS), store.path())
+            .fetch_with(&client(cache.path()))
+            .await;
+        assert!(fetched.is_err());
+    }
+
+    #[tokio::test]
+    async fn wait_for_slow_pages() {
+        let site = synthetic_site().await;
+        site.fault(JIRACHI, Fault::Slow(Duration::from_millis(300)));
+        let cache = tempfile::tempdir().unwrap();
+        let (_store, data) = fetch(&site, &client(cache.path())).await;
+        assert_eq!(set_names(&data).len(), 3);
+    }
+
+    #[tokio::test]
+    async fn cache_pages_between_fetches() {
+        let site = synthetic_site().await;
+        let cache = tempfile::tempdir().unwrap();
+        let client = client(cache.path());
+        let (_first_store, first) = fetch(&site, &client).await;
+        let (_second_store, second) = fetch(&site, &client).await;
+
+        assert_eq!(structure(&first), structure(&second));
+        for path in [SETS, BRILLIANT_STARS, ARCEUS, JIRACHI] {
+            assert_eq!(site.hits(path), 1, "{} was fetched again", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn warm_only_uses_the_cache_alone() {
+        let site = synthetic_site().await;
+        let cache = tempfile::tempdir().unwrap();
+        let warm_only = HttpOptions {
+            cache_policy: CachePolicy::WarmOnly,
+            ..options(cache.path())
+        };
+        let store = tempfile::tempdir().unwrap();
+        let cold = DataFetcher::new(&site.url(SETS), store.path())
+            .fetch_with(&client_of(&warm_only))
+            .await;
+        assert!(cold.is_err());
+        assert_eq!(site.hits(SETS), 0);
+
+        let (_store, warmed) = fetch(&site, &client(cache.path())).await;
+        let (_store, warm) = fetch(&site, &client_of(&warm_only)).await;
+        assert_eq!(structure(&warmed), structure(&warm));
+        assert_eq!(site.hits(SETS), 1);
+    }
+
+    #[tokio::test]
+    async fn refetch_pages_past_their_max_age() {
+        let site = synthetic_site().await;
+        let cache = tempfile::tempdir().unwrap();
+        let client = client_of(&HttpOptions {
+            max_age: Some(0),
+            ..options(cache.path())
+        });
+        fetch(&site, &client).await;
+        fetch(&site, &client).await;
+        assert_eq!(site.hits(SETS), 2);
+        assert_eq!(site.hits(JIRACHI), 2);
+    }
+
+    #[tokio::test]
+    async fn refresh_and_no_store_skip_the_cache() {
+        let site = synthetic_site().await;
+        let cache = tempfile::tempdir().unwrap();
+        for cache_policy in [
+            CachePolicy::NoStore,
+            CachePolicy::Refresh,
+            CachePolicy::Refresh,
+        ] {
+            let client = client_of(&HttpOptions {
+                cache_policy,
+                ..options(cache.path())
+            });
+            fetch(&site, &client).await;
+        }
+        assert_eq!(site.hits(SETS), 3);
+
+        fetch(&site, &client(cache.path())).await;
+        assert_eq!(site.hits(SETS), 3);
+    }
+
+    /// The mock site with Arceus printing a regulation mark the parser doesn't know
+    async fn drifted_site() -> MockSite {
+        let site = synthetic_site().await;
+        let page = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join(FIXTURES)
+            .join(ARCEUS.trim_matches('/'))
+            .with_extension("html");
+        let page = std::fs::read_to_string(page).unwrap().replace(
+            r#"regulation-mark/f/">F</a>"#,
+            r#"regulation-mark/z/">Z</a>"#,
+        );
+        site.page(ARCEUS, &page);
+        site
+    }
+
+    #[tokio::test]
+    async fn report_vocabulary_drift() {
+        let site = drifted_site().await;
+        let cache = tempfile::tempdir().unwrap();
+        let (store, data) = fetch(&site, &client(cache.path())).await;
+
+        assert_eq!(
+            set_names(&data),
+            vec!["Brilliant Stars", "Team Up", "Celestial Storm"]
+        );
+        let arceus = &data.all_series[0].sets[0].cards[0];
+        assert_eq!(arceus.regulation_mark.as_deref(), Some("Z"));
+        let report = VocabularyDrift::load(store.path().join(DRIFT_REPORT))
+            .await
+            .unwrap();
+        assert_eq!(report.summary(), "mark: Z (1)");
+
+        // Stored sets aren't parsed again, so the counts only grow with newly fetched cards
+        std::fs::remove_file(store.path().join("sword_shield/brilliant_stars.json")).unwrap();
+        DataFetcher::new(&site.url(SETS), store.path())
+            .fetch_with(&client(cache.path()))
+            .await
+            .unwrap();
+        let report = VocabularyDrift::load(store.path().join(DRIFT_REPORT))
+            .await
+            .unwrap();
+        assert_eq!(report.summary(), "mark: Z (2)");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn report_vocabulary_drift_of_failed_fetches() {
+        let site = drifted_site().await;
+        let cache = tempfile::tempdir().unwrap();
+        let store = tempfile::tempdir().unwrap();
+        // A dangling link where the second series goes fails the fetch after the first one
+        std::os::unix::fs::symlink(store.path().join("nowhere"), store.path().join("sun_moon"))
+            .unwrap();
+
+        let fetched = DataFetcher::new(&site.url(SETS), store.path())
+            .fetch_with(&client(cache.path()))
+            .await;
+        assert!(fetched.is_err());
+        let report = VocabularyDrift::load(store.path().join(DRIFT_REPORT))
+            .await
+            .unwrap();
+        assert_eq!(report.summary(), "mark: Z (1)");
+    }
+
+    #[tokio::test]
+    async fn resume_interrupted_sets() {
+        let site = synthetic_site().await;
+        site.fault(MEWTWO, Fault::ServerError { times: 3 });
+        let cache = tempfile::tempdir().unwrap();
+        let client = client_of(&HttpOptions {
+            cache_policy: CachePolicy::NoStore,
+            ..options(cache.path())
+        });
+        let store = tempfile::tempdir().unwrap();
+        let series = store.path().join("sword_shield");
+        let checkpoint = series.join("brilliant_stars.partial.jsonl");
+
+        let first = DataFetcher::new(&site.url(SETS), store.path())
+            .fetch_with(&client)
+            .await
+            .unwrap();
+        assert_eq!(set_names(&first), vec!["Team Up", "Celestial Storm"]);
+        assert!(!series.join("brilliant_stars.json").exists());
+        let checkpointed = std::fs::read_to_string(&checkpoint).unwrap();
+        assert_eq!(checkpointed.lines().count(), 1);
+
+        // An interruption in the middle of writing a card
+        std::fs::write(&checkpoint, format!("{}{{\"url\": \"http", checkpointed)).unwrap();
+        let second = DataFetcher::new(&site.url(SETS), store.path())
+            .fetch_with(&client)
+            .await
+            .unwrap();
+        assert_eq!(
+            set_names(&second),
+            vec!["Brilliant Stars", "Team Up", "Celestial Storm"]
+        );
+        assert_eq!(site.hits(ARCEUS), 1);
+        assert_eq!(site.hits(MEWTWO), 4);
+        assert!(series.join("brilliant_stars.json").exists());
+        assert!(!checkpoint.exists());
+    }
+}
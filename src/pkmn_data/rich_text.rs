@@ -0,0 +1,237 @@
+use anyhow::{anyhow, bail, Result};
+use ego_tree::NodeRef;
+use scraper::{ElementRef, Node};
+use selectors::attr::CaseSensitivity;
+use std::ops::Deref;
+
+/// The symbols pkmncards writes energy as and the energy they stand for
+pub(super) const ENERGY_SYMBOLS: [(&str, &str); 11] = [
+    ("{G}", "Grass"),
+    ("{W}", "Water"),
+    ("{R}", "Fire"),
+    ("{L}", "Lightning"),
+    ("{P}", "Psychic"),
+    ("{F}", "Fighting"),
+    ("{D}", "Darkness"),
+    ("{M}", "Metal"),
+    ("{Y}", "Fairy"),
+    ("{N}", "Dragon"),
+    ("{C}", "Colorless"),
+];
+
+/// How the text of attacks, abilities and rules is written out
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default, clap::ValueEnum, strum::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum TextFormat {
+    /// Energy as words and no markup, the way ptcgio writes card text
+    #[default]
+    Plain,
+    /// Emphasis and card links as markdown
+    Markdown,
+    /// Energy as the `{G}` style symbols printed on cards
+    Symbols,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum Segment {
+    Text(String),
+    /// An energy symbol as its `{G}` symbol and the energy's name
+    Energy {
+        symbol: &'static str,
+        name: &'static str,
+    },
+    /// A link to another card, usually its name
+    CardLink {
+        text: String,
+        url: String,
+    },
+    Emphasis(Vec<Segment>),
+    LineBreak,
+}
+
+/// Card text with the energy symbols, links and emphasis pkmncards marks up in it
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+pub struct RichText(pub Vec<Segment>);
+
+impl RichText {
+    pub fn parse(element: ElementRef) -> Result<Self> {
+        Ok(RichText(segments(*element)?))
+    }
+
+    /// The text after the first `breaks` line breaks, dropping the label, name and cost lines
+    /// of abilities and attacks
+    pub fn after_breaks(self, breaks: usize) -> Self {
+        let mut seen = 0;
+        RichText(
+            self.0
+                .into_iter()
+                .skip_while(|segment| {
+                    if seen == breaks {
+                        return false;
+                    }
+                    if *segment == Segment::LineBreak {
+                        seen += 1;
+                    }
+                    true
+                })
+                .collect(),
+        )
+    }
+
+    pub fn render(&self, format: TextFormat) -> String {
+        let mut output = String::new();
+        render_segments(&self.0, format, &mut output);
+        output
+            .lines()
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim()
+            .to_string()
+    }
+}
+
+fn segments(node: NodeRef<Node>) -> Result<Vec<Segment>> {
+    let mut parsed = Vec::new();
+    for child in node.children() {
+        match child.value() {
+            Node::Text(text) => parsed.push(Segment::Text(clean(text.deref()))),
+            Node::Element(element) => {
+                let element_ref = ElementRef::wrap(child).unwrap();
+                match element.name() {
+                    "br" => parsed.push(Segment::LineBreak),
+                    "abbr"
+                        if element
+                            .has_class("ptcg-symbol-name", CaseSensitivity::CaseSensitive) =>
+                    {
+                        parsed.push(energy(element_ref)?)
+                    }
+                    "a" => parsed.push(Segment::CardLink {
+                        text: clean(&element_ref.text().collect::<String>()),
+                        url: element.attr("href").unwrap_or_default().to_string(),
+                    }),
+                    "em" | "i" => parsed.push(Segment::Emphasis(segments(child)?)),
+                    _ => parsed.extend(segments(child)?),
+                }
+            }
+            Node::Comment(_) | Node::ProcessingInstruction(_) => {}
+            _ => bail!("Unknown node type in card text"),
+        }
+    }
+    Ok(parsed)
+}
+
+/// Reads an energy symbol either as pkmncards serves it, `{G}`, or with the symbol already
+/// replaced by the energy's name
+fn energy(element: ElementRef) -> Result<Segment> {
+    let text = element.text().collect::<String>();
+    let text = text.trim();
+    ENERGY_SYMBOLS
+        .iter()
+        .find(|(symbol, name)| *symbol == text || *name == text)
+        .map(|(symbol, name)| Segment::Energy { symbol, name })
+        .ok_or(anyhow!("Unknown energy symbol: {}", text))
+}
+
+/// Decodes entities and straightens the quotes the same way `clean_text` does, without
+/// trimming so words either side of markup stay apart. Newlines are only whitespace, lines
+/// are broken by `<br>`.
+fn clean(text: &str) -> String {
+    html_escape::decode_html_entities(text)
+        .chars()
+        .map(|ch| match ch {
+            '’' => '\'',
+            '“' | '”' => '"',
+            '\n' => ' ',
+            c => c,
+        })
+        .collect()
+}
+
+fn render_segments(segments: &[Segment], format: TextFormat, output: &mut String) {
+    for segment in segments {
+        match segment {
+            Segment::Text(text) if format == TextFormat::Markdown => {
+                output.push_str(&escape_markdown(text))
+            }
+            Segment::Text(text) => output.push_str(text),
+            Segment::Energy { symbol, .. } if format == TextFormat::Symbols => {
+                output.push_str(symbol)
+            }
+            Segment::Energy { name, .. } => output.push_str(name),
+            Segment::CardLink { text, url } if format == TextFormat::Markdown => {
+                output.push_str(&format!("[{}]({})", escape_markdown(text), url))
+            }
+            Segment::CardLink { text, .. } => output.push_str(text),
+            Segment::Emphasis(inner) if format == TextFormat::Markdown => {
+                output.push('*');
+                render_segments(inner, format, output);
+                output.push('*');
+            }
+            Segment::Emphasis(inner) => render_segments(inner, format, output),
+            Segment::LineBreak => output.push('\n'),
+        }
+    }
+}
+
+fn escape_markdown(text: &str) -> String {
+    text.chars()
+        .flat_map(|ch| match ch {
+            '*' | '_' | '[' | ']' | '\\' => vec!['\\', ch],
+            ch => vec![ch],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scraper::{Html, Selector};
+
+    fn parse(html: &str) -> RichText {
+        let fragment = Html::parse_fragment(html);
+        let selector = Selector::parse("p").unwrap();
+        RichText::parse(fragment.select(&selector).next().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parse_segments() {
+        let text = parse(
+            r#"<p><abbr title="Fire" class="ptcg-font ptcg-symbol-name">{R}</abbr> <span>Flare</span> : 30<br>
+Search your deck for a <a href="https://pkmncards.com/name/charmander/">Charmander</a> <em>(once per turn)</em>.</p>"#,
+        );
+        assert_eq!(
+            text.after_breaks(1),
+            RichText(vec![
+                Segment::Text(" Search your deck for a ".to_string()),
+                Segment::CardLink {
+                    text: "Charmander".to_string(),
+                    url: "https://pkmncards.com/name/charmander/".to_string()
+                },
+                Segment::Text(" ".to_string()),
+                Segment::Emphasis(vec![Segment::Text("(once per turn)".to_string())]),
+                Segment::Text(".".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn render_formats() {
+        let text = parse(
+            r#"<p>Attach a <abbr title="Water" class="ptcg-font ptcg-symbol-name">Water</abbr> Energy to <a href="https://pkmncards.com/name/squirtle/">Squirtle</a>.<br>
+<em>This_is *fine*</em></p>"#,
+        );
+        assert_eq!(
+            text.render(TextFormat::Plain),
+            "Attach a Water Energy to Squirtle.\nThis_is *fine*"
+        );
+        assert_eq!(
+            text.render(TextFormat::Symbols),
+            "Attach a {W} Energy to Squirtle.\nThis_is *fine*"
+        );
+        assert_eq!(
+            text.render(TextFormat::Markdown),
+            "Attach a Water Energy to [Squirtle](https://pkmncards.com/name/squirtle/).\n*This\\_is \\*fine\\**"
+        );
+    }
+}
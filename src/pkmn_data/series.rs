@@ -1,4 +1,7 @@
+use super::drift::VocabularyDrift;
+use super::rich_text::TextFormat;
 use super::set::SetFetcher;
+use super::SetListing;
 use crate::pkmn_data::set::Set;
 use anyhow::Result;
 use heck::ToSnekCase;
@@ -6,6 +9,7 @@ use reqwest_middleware::ClientWithMiddleware;
 use scraper::{ElementRef, Selector};
 use selectors::Element;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 
 pub(super) struct SeriesFetcher {
     series: String,
@@ -28,7 +32,12 @@ impl Series {
 }
 
 impl SeriesFetcher {
-    pub(super) fn new(series_ref: ElementRef, client: &ClientWithMiddleware) -> Result<Self> {
+    pub(super) fn new(
+        series_ref: ElementRef,
+        client: &ClientWithMiddleware,
+        text_format: TextFormat,
+        drift: &Arc<Mutex<VocabularyDrift>>,
+    ) -> Result<Self> {
         let raw_block_name = series_ref.inner_html();
         let series_name = html_escape::decode_html_entities(&raw_block_name).to_string();
         log::trace!("{}", series_name);
@@ -43,11 +52,25 @@ impl SeriesFetcher {
             series: series_name,
             set_fetchers: sets
                 .select(&set_selector)
-                .map(|set| SetFetcher::new(set, client))
+                .map(|set| SetFetcher::new(set, client, text_format, drift))
                 .collect::<Result<Vec<SetFetcher>>>()?,
         })
     }
 
+    pub(super) async fn list_sets(&self, base_path: &Path) -> Result<Vec<SetListing>> {
+        let path = base_path.join(self.series.to_snek_case());
+        let mut listings = Vec::new();
+        for set_fetcher in self.set_fetchers.iter() {
+            listings.push(SetListing {
+                name: set_fetcher.set_name.clone(),
+                code: set_fetcher.set_abbreviation.clone(),
+                series: self.series.clone(),
+                stored: set_fetcher.stored(&path).await?,
+            });
+        }
+        Ok(listings)
+    }
+
     pub(super) async fn fetch(&self, base_path: &Path) -> Result<Series> {
         let path = base_path.join(self.series.to_snek_case());
         if !path.exists() {
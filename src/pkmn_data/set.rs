@@ -1,13 +1,17 @@
 use super::card::CardFetcher;
+use super::drift::VocabularyDrift;
+use super::rich_text::TextFormat;
 use crate::ptcgio_data::Card;
-use anyhow::{bail, Result};
-use futures::stream::FuturesOrdered;
+use anyhow::{bail, Context, Result};
+use futures::stream::FuturesUnordered;
 use heck::ToSnekCase;
 use itertools::Itertools;
 use regex::Regex;
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
-use std::path::Path;
-use tokio::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::fs::{File, OpenOptions};
 
 use crate::pkmn_data::extractors::extract_text;
 use reqwest_middleware::ClientWithMiddleware;
@@ -19,14 +23,28 @@ use tokio_stream::StreamExt;
 pub(super) struct SetFetcher {
     url: String,
     pub set_name: String,
-    set_abbreviation: Option<String>,
+    pub(super) set_abbreviation: Option<String>,
     client: ClientWithMiddleware,
+    text_format: TextFormat,
+    drift: Arc<Mutex<VocabularyDrift>>,
+}
+
+/// A line of a set's checkpoint, a card with the page it was fetched from and its release date
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    url: String,
+    card: Card,
+    released: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
 pub struct Set {
     pub name: String,
     pub cards: Vec<Card>,
+    /// The date the earliest of the cards was released, in ptcgio's `YYYY/MM/DD`. Sets stored
+    /// before release dates were kept have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub released: Option<String>,
 }
 
 impl Display for Set {
@@ -50,12 +68,28 @@ impl Set {
         Self {
             name: name.to_string(),
             cards,
+            released: None,
         }
     }
+
+    pub async fn load(path: impl AsRef<Path>) -> Result<Set> {
+        let path = path.as_ref();
+        let file = File::open(path).await?;
+        let mut reader = BufReader::new(file);
+        let mut buffer: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut buffer).await?;
+        serde_json::from_slice(&buffer)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
 }
 
 impl SetFetcher {
-    pub(super) fn new(set_ref: ElementRef, client: &ClientWithMiddleware) -> Result<Self> {
+    pub(super) fn new(
+        set_ref: ElementRef,
+        client: &ClientWithMiddleware,
+        text_format: TextFormat,
+        drift: &Arc<Mutex<VocabularyDrift>>,
+    ) -> Result<Self> {
         let re = Regex::new(r"(?<set_name>.*?)(\s\((?<set_code>.*)\))?$")?;
         let set_name_and_code = extract_text(set_ref);
         log::trace!("set_name_and_code: {set_name_and_code}");
@@ -69,39 +103,133 @@ impl SetFetcher {
             set_name,
             set_abbreviation: set_code,
             client: client.clone(),
+            text_format,
+            drift: drift.clone(),
         })
     }
 
-    pub(super) async fn fetch(&self, series: &Path) -> Result<Set> {
-        let path = series
+    fn store_path(&self, series: &Path) -> PathBuf {
+        series
             .join(self.set_name.to_snek_case())
-            .with_extension("json");
+            .with_extension("json")
+    }
+
+    /// The set as previously fetched into the store, if it has been
+    pub(super) async fn stored(&self, series: &Path) -> Result<Option<Set>> {
+        let path = self.store_path(series);
         if path.exists() {
-            let file = File::open(path).await?;
-            let mut reader = BufReader::new(file);
-            let mut buffer: Vec<u8> = Vec::new();
-            reader.read_to_end(&mut buffer).await?;
-            Ok(serde_json::from_slice(&buffer)?)
+            Ok(Some(Set::load(&path).await?))
         } else {
-            let fetchers = self.get_card_fetchers().await?;
-            let fetched = fetchers
-                .iter()
-                .map(|fetcher| fetcher.fetch())
-                .collect::<FuturesOrdered<_>>();
-
-            let set = Set::new(
-                &self.set_name,
-                fetched.collect::<Result<Vec<Card>>>().await?,
-            );
-
-            let file = File::create(path).await?;
-            let mut writer = BufWriter::new(file);
-            println!("Set: {}", &set);
-            let output = serde_json::to_vec_pretty(&set)?;
-            writer.write_all(&output).await?;
-            writer.flush().await?;
-            Ok(set)
+            Ok(None)
+        }
+    }
+
+    /// Where the cards of a set still being fetched are kept, one JSON line per card
+    fn checkpoint_path(&self, series: &Path) -> PathBuf {
+        series
+            .join(self.set_name.to_snek_case())
+            .with_extension("partial.jsonl")
+    }
+
+    /// The cards a previous, interrupted fetch of the set got to. A line cut off by the
+    /// interruption is dropped so the card is fetched again.
+    async fn checkpointed(&self, path: &Path) -> Result<HashMap<String, Checkpoint>> {
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+        let file = File::open(path).await?;
+        let mut reader = BufReader::new(file);
+        let mut buffer = String::new();
+        reader.read_to_string(&mut buffer).await?;
+        let complete = buffer.rfind('\n').map_or(0, |end| end + 1);
+        if complete < buffer.len() {
+            let file = OpenOptions::new().write(true).open(path).await?;
+            file.set_len(complete as u64).await?;
+            buffer.truncate(complete);
+        }
+        Ok(buffer
+            .lines()
+            .filter_map(|line| match serde_json::from_str::<Checkpoint>(line) {
+                Ok(checkpoint) => Some((checkpoint.url.clone(), checkpoint)),
+                Err(e) => {
+                    log::warn!("Dropping a line of {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Fetches the cards of the set, resuming from its checkpoint if a previous fetch was
+    /// interrupted. Every card is checkpointed as soon as it's fetched and the set is only
+    /// written to the store once all of them are.
+    pub(super) async fn fetch(&self, series: &Path) -> Result<Set> {
+        if let Some(set) = self.stored(series).await? {
+            return Ok(set);
         }
+        let checkpoint_path = self.checkpoint_path(series);
+        let mut fetched = self.checkpointed(&checkpoint_path).await?;
+        let fetchers = self.get_card_fetchers().await?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&checkpoint_path)
+            .await?;
+        let mut checkpoint = BufWriter::new(file);
+        let mut pending = fetchers
+            .iter()
+            .filter(|fetcher| !fetched.contains_key(&fetcher.url))
+            .map(|fetcher| async move { (fetcher.url.clone(), fetcher.fetch().await) })
+            .collect::<FuturesUnordered<_>>();
+        let mut failure = None;
+        while let Some((url, card)) = pending.next().await {
+            match card {
+                Ok((card, released)) => {
+                    let entry = Checkpoint {
+                        url,
+                        card,
+                        released,
+                    };
+                    let mut line = serde_json::to_vec(&entry)?;
+                    line.push(b'\n');
+                    checkpoint.write_all(&line).await?;
+                    checkpoint.flush().await?;
+                    fetched.insert(entry.url.clone(), entry);
+                }
+                Err(e) => {
+                    failure.get_or_insert(e);
+                }
+            }
+        }
+        if let Some(e) = failure {
+            return Err(e.context(format!(
+                "{} cards of {} are checkpointed in {}",
+                fetched.len(),
+                self.set_name,
+                checkpoint_path.display()
+            )));
+        }
+
+        let mut set = Set::new(
+            &self.set_name,
+            fetchers
+                .iter()
+                .map(|fetcher| fetched[&fetcher.url].card.clone())
+                .collect(),
+        );
+        // ptcgio's dates sort like the days they are
+        set.released = fetched.values().map(|entry| entry.released.clone()).min();
+        println!("Set: {}", &set);
+        let path = self.store_path(series);
+        let partial = path.with_extension("json.partial");
+        let file = File::create(&partial).await?;
+        let mut writer = BufWriter::new(file);
+        let output = serde_json::to_vec_pretty(&set)?;
+        writer.write_all(&output).await?;
+        writer.flush().await?;
+        tokio::fs::rename(&partial, &path).await?;
+        tokio::fs::remove_file(&checkpoint_path).await?;
+        Ok(set)
     }
 
     async fn get_card_fetchers(&self) -> Result<Vec<CardFetcher>> {
@@ -123,7 +251,7 @@ impl SetFetcher {
         let doc = Html::parse_document(&set_page);
         Ok(doc
             .select(&card_selector)
-            .map(|card_ref| CardFetcher::new(card_ref, &self.client))
+            .map(|card_ref| CardFetcher::new(card_ref, &self.client, self.text_format, &self.drift))
             .collect())
     }
 }
@@ -0,0 +1,176 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::convert::Infallible;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// What the sign after an attack's damage means
+#[derive(
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Debug,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    strum::Display,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Modifier {
+    None,
+    /// The attack can do more than the base, "30+"
+    Plus,
+    /// The base is multiplied, "20×"
+    Times,
+    /// The attack can do less than the base, "120-"
+    Minus,
+}
+
+impl Modifier {
+    fn symbol(&self) -> &'static str {
+        match self {
+            Modifier::None => "",
+            Modifier::Plus => "+",
+            Modifier::Times => "×",
+            Modifier::Minus => "-",
+        }
+    }
+}
+
+/// The damage printed on an attack. It converts back to exactly the string it was parsed
+/// from, the form ptcgio uses.
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Damage {
+    Value {
+        base: u32,
+        modifier: Modifier,
+    },
+    /// Damage that isn't a number, like the "?" of some Unown
+    Other(String),
+}
+
+impl From<&str> for Damage {
+    fn from(value: &str) -> Self {
+        let (digits, modifier) = match value.char_indices().last() {
+            Some((idx, '+')) => (&value[..idx], Modifier::Plus),
+            Some((idx, '×')) => (&value[..idx], Modifier::Times),
+            Some((idx, '-')) => (&value[..idx], Modifier::Minus),
+            _ => (value, Modifier::None),
+        };
+        // Only plain digits, so "+30" or "030" stay as they are written
+        let base = digits
+            .parse::<u32>()
+            .ok()
+            .filter(|base| base.to_string() == digits);
+        match base {
+            Some(base) => Damage::Value { base, modifier },
+            None => Damage::Other(value.to_string()),
+        }
+    }
+}
+
+impl FromStr for Damage {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Damage::from(s))
+    }
+}
+
+impl Display for Damage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Damage::Value { base, modifier } => write!(f, "{}{}", base, modifier.symbol()),
+            Damage::Other(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+/// Orders by base amount, then modifier, with damage that isn't a number last
+impl Ord for Damage {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (
+                Damage::Value { base, modifier },
+                Damage::Value {
+                    base: other_base,
+                    modifier: other_modifier,
+                },
+            ) => base.cmp(other_base).then(modifier.cmp(other_modifier)),
+            (Damage::Value { .. }, Damage::Other(_)) => Ordering::Less,
+            (Damage::Other(_), Damage::Value { .. }) => Ordering::Greater,
+            (Damage::Other(text), Damage::Other(other_text)) => text.cmp(other_text),
+        }
+    }
+}
+
+impl PartialOrd for Damage {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_damage() {
+        assert_eq!(
+            Damage::from("30+"),
+            Damage::Value {
+                base: 30,
+                modifier: Modifier::Plus
+            }
+        );
+        assert_eq!(
+            Damage::from("20×"),
+            Damage::Value {
+                base: 20,
+                modifier: Modifier::Times
+            }
+        );
+        assert_eq!(
+            Damage::from("120-"),
+            Damage::Value {
+                base: 120,
+                modifier: Modifier::Minus
+            }
+        );
+        assert_eq!(
+            Damage::from("200"),
+            Damage::Value {
+                base: 200,
+                modifier: Modifier::None
+            }
+        );
+        assert_eq!(Damage::from("?"), Damage::Other("?".to_string()));
+    }
+
+    #[test]
+    fn serialize_damage() {
+        let damages = [Damage::from("30+"), Damage::from("?")];
+        let json = serde_json::to_value(&damages).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([{"base": 30, "modifier": "plus"}, "?"])
+        );
+        assert_eq!(
+            serde_json::from_value::<Vec<Damage>>(json).unwrap(),
+            damages
+        );
+    }
+
+    #[test]
+    fn damage_round_trips() {
+        for damage in ["30+", "20×", "120-", "200", "?", "+", "030", "10x"] {
+            assert_eq!(Damage::from(damage).to_string(), damage);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::{anyhow, bail, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use strum::{Display, EnumString};
+
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Clone, Copy, EnumString, Display)]
+pub enum EnergyColor {
+    Grass,
+    Fire,
+    Water,
+    Lightning,
+    Psychic,
+    Fighting,
+    Darkness,
+    Metal,
+    Fairy,
+    Colorless,
+}
+
+/// What ptcgio puts in the cost of an attack that costs nothing
+const FREE: &str = "Free";
+
+/// The energy an attack costs, in the order it is printed. An empty cost is a free attack;
+/// a cost that isn't known at all is `None` wherever it is optional.
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+pub struct EnergyCost {
+    colors: Vec<EnergyColor>,
+}
+
+impl EnergyCost {
+    pub fn new(colors: Vec<EnergyColor>) -> Self {
+        EnergyCost { colors }
+    }
+
+    /// Reads the `cost` of a ptcgio attack, `None` if the attack has none
+    pub fn from_ptcgio(attack: &BTreeMap<String, Value>) -> Result<Option<Self>> {
+        let Some(cost) = attack.get("cost") else {
+            return Ok(None);
+        };
+        let colors = cost
+            .as_array()
+            .ok_or(anyhow!("The cost {} is not a list", cost))?
+            .iter()
+            .map(|color| match color.as_str() {
+                Some(FREE) => Ok(None),
+                Some(color) => Ok(Some(EnergyColor::from_str(color)?)),
+                None => bail!("The energy {} is not a string", color),
+            })
+            .filter_map(Result::transpose)
+            .collect::<Result<Vec<EnergyColor>>>()?;
+        Ok(Some(EnergyCost { colors }))
+    }
+
+    /// The cost as ptcgio lists it, `["Free"]` for a free attack
+    pub fn to_ptcgio(&self) -> Vec<Value> {
+        if self.is_free() {
+            return vec![Value::String(FREE.to_string())];
+        }
+        self.colors
+            .iter()
+            .map(|color| Value::String(color.to_string()))
+            .collect()
+    }
+
+    pub fn is_free(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// The number of energy the attack costs, ptcgio's `convertedEnergyCost`
+    pub fn total(&self) -> usize {
+        self.colors.len()
+    }
+
+    /// The number of energy of each color, Colorless included
+    pub fn counts(&self) -> BTreeMap<EnergyColor, usize> {
+        let mut counts = BTreeMap::new();
+        for color in &self.colors {
+            *counts.entry(*color).or_default() += 1;
+        }
+        counts
+    }
+
+    /// Whether `energies`, one color per energy, pay for the attack. Colored energy has to be
+    /// paid by its own color and Colorless by whatever is left over.
+    pub fn payable_with(&self, energies: &[EnergyColor]) -> bool {
+        let mut available = EnergyCost::new(energies.to_vec()).counts();
+        let mut colorless = 0;
+        for (color, count) in self.counts() {
+            if color == EnergyColor::Colorless {
+                colorless += count;
+                continue;
+            }
+            match available.get_mut(&color) {
+                Some(have) if *have >= count => *have -= count,
+                _ => return false,
+            }
+        }
+        available.values().sum::<usize>() >= colorless
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ptcgio_cost() {
+        let attack = serde_json::from_str::<BTreeMap<String, Value>>(
+            r#"{"cost": ["Fire", "Fire", "Colorless"], "name": "Flare", "convertedEnergyCost": 3}"#,
+        )
+        .unwrap();
+        let cost = EnergyCost::from_ptcgio(&attack).unwrap().unwrap();
+        assert_eq!(
+            cost.counts(),
+            BTreeMap::from([(EnergyColor::Fire, 2), (EnergyColor::Colorless, 1)])
+        );
+        assert_eq!(cost.total(), 3);
+        assert_eq!(cost.to_ptcgio(), attack["cost"].as_array().unwrap().clone());
+    }
+
+    #[test]
+    fn free_and_missing_costs() {
+        let free =
+            serde_json::from_str::<BTreeMap<String, Value>>(r#"{"cost": ["Free"]}"#).unwrap();
+        let cost = EnergyCost::from_ptcgio(&free).unwrap().unwrap();
+        assert!(cost.is_free());
+        assert_eq!(cost.total(), 0);
+        assert_eq!(cost.to_ptcgio(), free["cost"].as_array().unwrap().clone());
+        let missing =
+            serde_json::from_str::<BTreeMap<String, Value>>(r#"{"name": "Tackle"}"#).unwrap();
+        assert_eq!(EnergyCost::from_ptcgio(&missing).unwrap(), None);
+    }
+
+    #[test]
+    fn payable_with() {
+        let cost = EnergyCost::new(vec![
+            EnergyColor::Water,
+            EnergyColor::Colorless,
+            EnergyColor::Colorless,
+        ]);
+        assert!(cost.payable_with(&[EnergyColor::Water, EnergyColor::Fire, EnergyColor::Water]));
+        assert!(!cost.payable_with(&[EnergyColor::Fire, EnergyColor::Fire, EnergyColor::Fire]));
+        assert!(!cost.payable_with(&[EnergyColor::Water, EnergyColor::Water]));
+        assert!(EnergyCost::default().payable_with(&[]));
+    }
+}
@@ -1,13 +1,20 @@
+pub mod damage;
+pub mod energy;
+
+pub use damage::Damage;
+pub use energy::{EnergyColor, EnergyCost};
+
 use anyhow::{Context, Result};
 use git2::Repository;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashMap};
+use std::collections::BTreeMap;
 use std::fs;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::{Path, PathBuf};
 
-#[derive(Serialize, Deserialize, Default, Debug, Eq, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug, Eq, PartialEq, Clone)]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
 pub struct Card {
@@ -36,24 +43,71 @@ pub struct Card {
     pub images: BTreeMap<String, String>,
     pub ancient_trait: Option<BTreeMap<String, String>>,
     pub regulation_mark: Option<String>,
+    /// The fields filled in from pages other than the card's own, only ever set on pkmncards
+    /// cards
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub enriched: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
 pub struct Set {
-    #[serde(default)]
+    #[serde(default, skip_serializing)]
     pub cards: Vec<Card>,
     pub id: String,
     pub name: String,
     pub series: String,
     pub printed_total: i32,
     pub total: i32,
-    pub legalities: HashMap<String, String>,
+    pub legalities: BTreeMap<String, String>,
     pub ptcgo_code: Option<String>,
     pub release_date: String,
     pub updated_at: String,
-    pub images: HashMap<String, String>,
+    pub images: BTreeMap<String, String>,
+}
+
+impl Card {
+    /// The cost of each of the card's attacks, `None` for an attack without one
+    pub fn attack_costs(&self) -> Result<Vec<Option<EnergyCost>>> {
+        self.attacks
+            .iter()
+            .flatten()
+            .map(|attack| {
+                EnergyCost::from_ptcgio(attack)
+                    .with_context(|| format!("Failed to read an attack cost of {}", self.id))
+            })
+            .collect()
+    }
+
+    /// The damage of each of the card's attacks, `None` for an attack that doesn't print any
+    pub fn attack_damages(&self) -> Vec<Option<Damage>> {
+        self.attacks
+            .iter()
+            .flatten()
+            .map(|attack| {
+                attack
+                    .get("damage")
+                    .and_then(serde_json::Value::as_str)
+                    .filter(|damage| !damage.is_empty())
+                    .map(Damage::from)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+impl Card {
+    /// A Pokémon with the id and name, numbered after the id, for tests to fill in the rest of
+    pub fn stub(id: &str, name: &str) -> Card {
+        Card {
+            id: id.to_string(),
+            name: name.to_string(),
+            supertype: "Pokémon".to_string(),
+            number: id.rsplit('-').next().unwrap().to_string(),
+            ..Card::default()
+        }
+    }
 }
 
 impl Set {
@@ -69,6 +123,8 @@ pub struct DataFetcher {
 
 pub struct Data {
     path: PathBuf,
+    /// Whether the checkout was cloned by us and should be deleted again once we're done
+    owned: bool,
     pub sets: Vec<Set>,
 }
 
@@ -101,21 +157,26 @@ impl DataFetcher {
 
     fn fetch_(&self) -> Result<Data> {
         Repository::clone(&self.url, &self.path).context("PTCG data repository failed to clone")?;
+        let mut data = Data::open(&self.path)?;
+        data.owned = true;
+        Ok(data)
+    }
+}
 
+impl Data {
+    /// Loads the data from an existing pokemon-tcg-data checkout, which is left in place
+    pub fn open(path: impl AsRef<Path>) -> Result<Data> {
+        let path = path.as_ref();
         let sets_file =
-            File::open(self.path.join("sets/en.json")).context("Failed to open sets file")?;
+            File::open(path.join("sets/en.json")).context("Failed to open sets file")?;
         let reader = BufReader::new(sets_file);
         let sets: Vec<Set> = serde_json::from_reader::<BufReader<File>, Vec<Set>>(reader)
             .context("Failed to parse the sets file")?
             .into_iter()
             .map(|mut set| {
-                let cards_file = File::open(
-                    self.path
-                        .join("cards/en")
-                        .join(&set.id)
-                        .with_extension("json"),
-                )
-                .context(format!("Failed to open {} cards file", &set.id))?;
+                let cards_file =
+                    File::open(path.join("cards/en").join(&set.id).with_extension("json"))
+                        .context(format!("Failed to open {} cards file", &set.id))?;
                 let r = BufReader::new(cards_file);
                 let cards = serde_json::from_reader::<BufReader<File>, Vec<Card>>(r)
                     .context(format!("Failed to parse {} cards file", &set.id))?;
@@ -126,14 +187,88 @@ impl DataFetcher {
             .collect::<Result<Vec<Set>>>()?;
 
         Ok(Data {
-            path: self.path.clone(),
+            path: path.to_path_buf(),
+            owned: false,
             sets,
         })
     }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
 }
 
 impl Drop for Data {
     fn drop(&mut self) {
-        fs::remove_dir_all(&self.path).expect("Failed to delete data directory");
+        if self.owned {
+            fs::remove_dir_all(&self.path).expect("Failed to delete data directory");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn read_attack_costs() {
+        let attack = |cost: Value| BTreeMap::from([("cost".to_string(), cost)]);
+        let card = Card {
+            attacks: Some(vec![
+                attack(json!(["Free"])),
+                attack(json!(["Water", "Colorless"])),
+                BTreeMap::new(),
+            ]),
+            ..Card::stub("base1-2", "Blastoise")
+        };
+        let costs = card.attack_costs().unwrap();
+        assert!(costs[0].as_ref().unwrap().is_free());
+        assert_eq!(costs[1].as_ref().unwrap().total(), 2);
+        assert_eq!(costs[2], None);
+        assert!(costs[1]
+            .as_ref()
+            .unwrap()
+            .payable_with(&[EnergyColor::Fire, EnergyColor::Water]));
+
+        let unknown = Card {
+            attacks: Some(vec![attack(json!(["Plasma"]))]),
+            ..card
+        };
+        let error = unknown.attack_costs().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed to read an attack cost of base1-2"
+        );
+    }
+
+    #[test]
+    fn read_attack_damages() {
+        let printed = ["30+", "20×", "120-", ""];
+        let card = Card {
+            attacks: Some(
+                printed
+                    .iter()
+                    .map(|damage| BTreeMap::from([("damage".to_string(), json!(damage))]))
+                    .chain([BTreeMap::new()])
+                    .collect(),
+            ),
+            ..Card::stub("base1-2", "Blastoise")
+        };
+        let damages = card.attack_damages();
+        assert_eq!(
+            damages[0],
+            Some(Damage::Value {
+                base: 30,
+                modifier: damage::Modifier::Plus
+            })
+        );
+        assert_eq!(damages[3], None);
+        assert_eq!(damages[4], None);
+        let written = damages
+            .iter()
+            .map(|damage| damage.as_ref().map(Damage::to_string).unwrap_or_default())
+            .collect::<Vec<_>>();
+        assert_eq!(written[..4], printed);
     }
 }
@@ -0,0 +1,571 @@
+use crate::ptcgio_data::{Card, EnergyColor, EnergyCost};
+use anyhow::{anyhow, bail, Result};
+use heck::ToLowerCamelCase;
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// A filter over cards like `supertype = "Pokémon" and weaknesses.value != "×2"`.
+///
+/// Fields are the card's JSON fields, in camelCase or snake_case, plus `set` for the name of the
+/// card's set. A dotted path reaches into objects and lists, and a comparison holds if any of
+/// the values it reaches satisfies it. `=`, `!=`, `in [..]` and `~` (contains, ignoring case)
+/// compare text, `<`, `<=`, `>` and `>=` numbers, numbers written as text like `hp` included.
+/// `payable_with(Water, Water, Fire)` holds for cards with an attack those energies pay for.
+/// Comparisons combine with `and`, `or`, `not` and parentheses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(FieldPath, Op, Literal),
+    In(FieldPath, Vec<Literal>),
+    PayableWith(Vec<EnergyColor>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Text(String),
+    Number(f64),
+    Bool(bool),
+    Null,
+}
+
+/// The steps from a card to some of its values, in the camelCase of the card's JSON
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldPath(Vec<String>);
+
+impl FromStr for FieldPath {
+    type Err = anyhow::Error;
+
+    fn from_str(path: &str) -> Result<Self> {
+        let steps = path
+            .split('.')
+            .map(|step| {
+                if step.is_empty() || !step.chars().all(|ch| ch.is_alphanumeric() || ch == '_') {
+                    bail!("Invalid field path: {}", path)
+                }
+                Ok(step.to_lower_camel_case())
+            })
+            .collect::<Result<Vec<String>>>()?;
+        Ok(FieldPath(steps))
+    }
+}
+
+impl Display for FieldPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.join("."))
+    }
+}
+
+/// A card the way queries see it, its JSON with the name of its set added as `set`
+pub struct Subject {
+    fields: Value,
+    attack_costs: Result<Vec<Option<EnergyCost>>>,
+}
+
+impl Subject {
+    pub fn new(set: &str, card: &Card) -> Result<Self> {
+        let mut fields = serde_json::to_value(card)?;
+        if let Value::Object(map) = &mut fields {
+            map.insert("set".to_string(), Value::String(set.to_string()));
+        }
+        Ok(Subject {
+            fields,
+            attack_costs: card.attack_costs(),
+        })
+    }
+
+    /// The card's JSON with its set
+    pub fn fields(&self) -> &Value {
+        &self.fields
+    }
+
+    /// Every value at `path`, with the items of lists on the way and at the end taken one by
+    /// one. Missing fields and nulls have no values.
+    pub fn values(&self, path: &FieldPath) -> Vec<&Value> {
+        let mut values = vec![&self.fields];
+        for step in &path.0 {
+            values = values
+                .into_iter()
+                .flat_map(items)
+                .filter_map(|value| value.get(step))
+                .collect();
+        }
+        values
+            .into_iter()
+            .flat_map(items)
+            .filter(|value| !value.is_null())
+            .collect()
+    }
+
+    /// The values at `path` as one line of text, for tables and CSV
+    pub fn text(&self, path: &FieldPath) -> String {
+        self.values(path)
+            .into_iter()
+            .map(text)
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+fn items(value: &Value) -> Vec<&Value> {
+    match value {
+        Value::Array(items) => items.iter().collect(),
+        value => vec![value],
+    }
+}
+
+impl Query {
+    pub fn matches(&self, subject: &Subject) -> bool {
+        self.expr.matches(subject)
+    }
+}
+
+impl Expr {
+    fn matches(&self, subject: &Subject) -> bool {
+        match self {
+            Expr::And(left, right) => left.matches(subject) && right.matches(subject),
+            Expr::Or(left, right) => left.matches(subject) || right.matches(subject),
+            Expr::Not(inner) => !inner.matches(subject),
+            Expr::Compare(path, op, Literal::Null) => {
+                let present = !subject.values(path).is_empty();
+                match op {
+                    Op::Eq => !present,
+                    Op::Ne => present,
+                    _ => false,
+                }
+            }
+            Expr::Compare(path, op, literal) => subject
+                .values(path)
+                .into_iter()
+                .any(|value| compare(value, *op, literal)),
+            Expr::In(path, literals) => subject.values(path).into_iter().any(|value| {
+                literals
+                    .iter()
+                    .any(|literal| compare(value, Op::Eq, literal))
+            }),
+            Expr::PayableWith(energies) => match &subject.attack_costs {
+                Ok(costs) => costs
+                    .iter()
+                    .flatten()
+                    .any(|cost| cost.payable_with(energies)),
+                Err(err) => {
+                    log::warn!("Treating the card as unpayable: {:#}", err);
+                    false
+                }
+            },
+        }
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn compare(value: &Value, op: Op, literal: &Literal) -> bool {
+    let literal_text = match literal {
+        Literal::Text(text) => text.clone(),
+        Literal::Number(number) => number.to_string(),
+        Literal::Bool(bool) => bool.to_string(),
+        Literal::Null => return false,
+    };
+    let numbers = match literal {
+        Literal::Number(literal) => number(value).map(|value| (value, *literal)),
+        _ => None,
+    };
+    match op {
+        Op::Eq | Op::Ne => {
+            let equal = match numbers {
+                Some((value, literal)) => value == literal,
+                None => text(value) == literal_text,
+            };
+            equal == (op == Op::Eq)
+        }
+        Op::Contains => text(value)
+            .to_lowercase()
+            .contains(&literal_text.to_lowercase()),
+        Op::Lt | Op::Le | Op::Gt | Op::Ge => match numbers {
+            Some((value, literal)) => match op {
+                Op::Lt => value < literal,
+                Op::Le => value <= literal,
+                Op::Gt => value > literal,
+                _ => value >= literal,
+            },
+            None => false,
+        },
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Number(f64),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 12] = [
+    "!=", "<=", ">=", "=", "<", ">", "~", "(", ")", "[", "]", ",",
+];
+
+/// Splits a query into tokens with the character offset each starts at
+fn tokenize(query: &str) -> Result<Vec<(usize, Token)>> {
+    let chars = query.chars().collect::<Vec<char>>();
+    let mut tokens = Vec::new();
+    let mut at = 0;
+    while at < chars.len() {
+        let start = at;
+        let ch = chars[at];
+        if ch.is_whitespace() {
+            at += 1;
+            continue;
+        }
+        if ch == '"' {
+            let mut text = String::new();
+            at += 1;
+            loop {
+                match chars.get(at) {
+                    None => bail!("Unterminated string starting at {}", start),
+                    Some('"') => break,
+                    Some('\\') => {
+                        let escaped = chars
+                            .get(at + 1)
+                            .ok_or(anyhow!("Unterminated string starting at {}", start))?;
+                        text.push(*escaped);
+                        at += 2;
+                    }
+                    Some(ch) => {
+                        text.push(*ch);
+                        at += 1;
+                    }
+                }
+            }
+            at += 1;
+            tokens.push((start, Token::Text(text)));
+        } else if ch.is_ascii_digit()
+            || (ch == '-' && chars.get(at + 1).is_some_and(char::is_ascii_digit))
+        {
+            at += 1;
+            while chars
+                .get(at)
+                .is_some_and(|ch| ch.is_ascii_digit() || *ch == '.')
+            {
+                at += 1;
+            }
+            let number = chars[start..at].iter().collect::<String>();
+            let number = number
+                .parse()
+                .map_err(|_| anyhow!("Invalid number {} at {}", number, start))?;
+            tokens.push((start, Token::Number(number)));
+        } else if ch.is_alphabetic() || ch == '_' {
+            while chars
+                .get(at)
+                .is_some_and(|ch| ch.is_alphanumeric() || *ch == '_' || *ch == '.')
+            {
+                at += 1;
+            }
+            tokens.push((start, Token::Word(chars[start..at].iter().collect())));
+        } else {
+            let rest = chars[at..].iter().take(2).collect::<String>();
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(**symbol))
+                .ok_or(anyhow!("Unexpected {:?} at {}", ch, start))?;
+            at += symbol.chars().count();
+            tokens.push((start, Token::Symbol(symbol)));
+        }
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent parser over the tokens, `or` binding loosest and `not` tightest
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.next)
+            .map_or(self.end, |(offset, _)| *offset)
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found =
+            matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword));
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn symbol(&mut self, symbol: &'static str) -> bool {
+        let found = self.peek() == Some(&Token::Symbol(symbol));
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<()> {
+        if !self.symbol(symbol) {
+            bail!("Expected {} at {}", symbol, self.offset());
+        }
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.symbol("(") {
+            let expr = self.or()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let offset = self.offset();
+        let path = match self.peek() {
+            Some(Token::Word(word)) if !is_keyword(word) => word.parse::<FieldPath>()?,
+            _ => bail!("Expected a field at {}", offset),
+        };
+        self.next += 1;
+        if self.symbol("(") {
+            if path.to_string() != "payableWith" {
+                bail!("Unknown function {} at {}", path, offset);
+            }
+            return self.payable_with();
+        }
+        if self.keyword("in") {
+            self.expect("[")?;
+            let mut literals = vec![self.literal()?];
+            while self.symbol(",") {
+                literals.push(self.literal()?);
+            }
+            self.expect("]")?;
+            return Ok(Expr::In(path, literals));
+        }
+        let op = match self.peek() {
+            Some(Token::Symbol("=")) => Op::Eq,
+            Some(Token::Symbol("!=")) => Op::Ne,
+            Some(Token::Symbol("<")) => Op::Lt,
+            Some(Token::Symbol("<=")) => Op::Le,
+            Some(Token::Symbol(">")) => Op::Gt,
+            Some(Token::Symbol(">=")) => Op::Ge,
+            Some(Token::Symbol("~")) => Op::Contains,
+            _ => bail!("Expected a comparison at {}", self.offset()),
+        };
+        self.next += 1;
+        Ok(Expr::Compare(path, op, self.literal()?))
+    }
+
+    /// The energies of `payable_with(..)` after its opening parenthesis
+    fn payable_with(&mut self) -> Result<Expr> {
+        let mut energies = Vec::new();
+        loop {
+            let offset = self.offset();
+            let energy = match self.peek() {
+                Some(Token::Word(word)) => word
+                    .parse::<EnergyColor>()
+                    .map_err(|_| anyhow!("Unknown energy {} at {}", word, offset))?,
+                _ => bail!("Expected an energy at {}", offset),
+            };
+            self.next += 1;
+            energies.push(energy);
+            if !self.symbol(",") {
+                break;
+            }
+        }
+        self.expect(")")?;
+        Ok(Expr::PayableWith(energies))
+    }
+
+    fn literal(&mut self) -> Result<Literal> {
+        let offset = self.offset();
+        let literal = match self.peek() {
+            Some(Token::Text(text)) => Literal::Text(text.clone()),
+            Some(Token::Number(number)) => Literal::Number(*number),
+            Some(Token::Word(word)) if word == "true" || word == "false" => {
+                Literal::Bool(word == "true")
+            }
+            Some(Token::Word(word)) if word == "null" => Literal::Null,
+            _ => bail!("Expected a value at {}", offset),
+        };
+        self.next += 1;
+        Ok(literal)
+    }
+}
+
+fn is_keyword(word: &str) -> bool {
+    ["and", "or", "not", "in"]
+        .iter()
+        .any(|keyword| word.eq_ignore_ascii_case(keyword))
+}
+
+impl FromStr for Query {
+    type Err = anyhow::Error;
+
+    fn from_str(query: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(query)?,
+            next: 0,
+            end: query.chars().count(),
+        };
+        let expr = parser.or()?;
+        if parser.peek().is_some() {
+            bail!("Unexpected input at {}", parser.offset());
+        }
+        Ok(Query { expr })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn pikachu() -> Subject {
+        let card = Card {
+            hp: Some("40".to_string()),
+            attacks: Some(vec![BTreeMap::from([(
+                "cost".to_string(),
+                json!(["Lightning", "Colorless"]),
+            )])]),
+            types: Some(vec!["Lightning".to_string()]),
+            weaknesses: Some(vec![BTreeMap::from([
+                ("type".to_string(), "Fighting".to_string()),
+                ("value".to_string(), "×2".to_string()),
+            ])]),
+            flavor_text: Some("When several of these Pokémon gather...".to_string()),
+            ..Card::stub("base1-58", "Pikachu")
+        };
+        Subject::new("Base", &card).unwrap()
+    }
+
+    fn matches(query: &str) -> bool {
+        query.parse::<Query>().unwrap().matches(&pikachu())
+    }
+
+    #[test]
+    fn match_comparisons() {
+        assert!(matches(
+            r#"supertype = "Pokémon" and set in ["Base", "Jungle"]"#
+        ));
+        assert!(!matches(r#"weaknesses.value != "×2""#));
+        assert!(matches(r#"weaknesses.type = "Fighting""#));
+        assert!(matches(r#"types = "Lightning""#));
+        assert!(matches("hp >= 40 and hp < 50 and number = 58"));
+        assert!(!matches("hp > 40"));
+        assert!(matches(r#"flavor_text ~ "SEVERAL""#));
+        assert!(matches("resistances = null and not hp = null"));
+        assert!(matches(
+            r#"(name = "Raichu" or name = "Pikachu") and not set = "Jungle""#
+        ));
+        assert!(!matches(r#"name = "Raichu" or name = "Pichu" and hp = 40"#));
+    }
+
+    #[test]
+    fn reject_malformed_queries() {
+        for (query, error) in [
+            (r#"name = "Pikachu"#, "Unterminated string starting at 7"),
+            (r#"name "Pikachu""#, "Expected a comparison at 5"),
+            ("name = ", "Expected a value at 7"),
+            ("(hp > 40", "Expected ) at 8"),
+            ("hp > 40 40", "Unexpected input at 8"),
+            ("and = 1", "Expected a field at 0"),
+            ("set in [1,]", "Expected a value at 10"),
+            ("hp # 4", "Unexpected '#' at 3"),
+            ("name(Water)", "Unknown function name at 0"),
+            ("payable_with(Plasma)", "Unknown energy Plasma at 13"),
+            ("payable_with()", "Expected an energy at 13"),
+            ("payable_with(Water", "Expected ) at 18"),
+        ] {
+            let parsed = query.parse::<Query>();
+            assert_eq!(
+                parsed.map_err(|err| err.to_string()),
+                Err(error.to_string()),
+                "{}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn match_payable_attacks() {
+        assert!(matches("payable_with(Lightning, Water)"));
+        assert!(matches("payableWith(Lightning, Lightning, Fire)"));
+        assert!(!matches("payable_with(Water, Water)"));
+        assert!(!matches("payable_with(Lightning)"));
+
+        let unpayable = Card {
+            attacks: Some(vec![BTreeMap::from([(
+                "cost".to_string(),
+                json!(["Plasma"]),
+            )])]),
+            ..Card::stub("base1-58", "Pikachu")
+        };
+        let subject = Subject::new("Base", &unpayable).unwrap();
+        let query = "payable_with(Water) or name = \"Pikachu\""
+            .parse::<Query>()
+            .unwrap();
+        assert!(query.matches(&subject));
+        assert!(!"payable_with(Water)"
+            .parse::<Query>()
+            .unwrap()
+            .matches(&subject));
+    }
+}
@@ -0,0 +1,527 @@
+use crate::decisions::Decisions;
+use crate::diff::{parse_path, set_at_path, value_at_path};
+use crate::ptcgio_data::{Card, Set};
+use anyhow::{Context, Result};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Ptcgio,
+    Pkmn,
+    /// The value was not taken from either dataset but computed while reconciling
+    Derived,
+    /// The value was set by a reviewer decision
+    Override,
+}
+
+/// Which dataset wins for each card field, keyed by the field's name in the ptcgio format.
+/// Fields without an entry use `default`. Whatever the preferred dataset is missing is filled
+/// in from the other one.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Policy {
+    default: Source,
+    fields: BTreeMap<String, Source>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            default: Source::Ptcgio,
+            fields: BTreeMap::from([
+                ("id".to_string(), Source::Ptcgio),
+                ("images".to_string(), Source::Ptcgio),
+                ("legalities".to_string(), Source::Ptcgio),
+                ("nationalPokedexNumbers".to_string(), Source::Ptcgio),
+                ("abilities".to_string(), Source::Pkmn),
+                ("ancientTrait".to_string(), Source::Pkmn),
+                ("attacks".to_string(), Source::Pkmn),
+                ("flavorText".to_string(), Source::Pkmn),
+                ("rules".to_string(), Source::Pkmn),
+            ]),
+        }
+    }
+}
+
+impl Policy {
+    pub async fn load(path: impl AsRef<Path>) -> Result<Policy> {
+        let file = File::open(path).await?;
+        let mut reader = BufReader::new(file);
+        let mut buffer: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut buffer).await?;
+        Ok(serde_json::from_slice(&buffer)?)
+    }
+
+    fn preferred(&self, field: &str) -> Source {
+        *self.fields.get(field).unwrap_or(&self.default)
+    }
+}
+
+/// The source each field of a reconciled card was taken from. Fields a reviewer decided on are
+/// recorded under the decided path, e.g. `attacks[0].damage`.
+pub type Provenance = BTreeMap<String, Source>;
+
+pub struct Reconciled {
+    /// Sets in the order they appear in ptcgio, each holding its reconciled cards
+    pub sets: Vec<Set>,
+    /// Per set id, the provenance of every card keyed by card id
+    pub provenance: BTreeMap<String, BTreeMap<String, Provenance>>,
+}
+
+/// Pairs up the cards of one set from both datasets by their printed number. When a number
+/// occurs more than once on a side the cards sharing it are paired by name first and then in
+/// order.
+pub fn pair_cards<'a>(
+    ptcgio: &'a [Card],
+    pkmn: &'a [Card],
+) -> Vec<(Option<&'a Card>, Option<&'a Card>)> {
+    let mut pkmn_by_number = pkmn.iter().into_group_map_by(|card| card.number.as_str());
+
+    let mut pairs = ptcgio
+        .iter()
+        .map(|card| {
+            let other = pkmn_by_number
+                .get_mut(card.number.as_str())
+                .and_then(|candidates| {
+                    let idx = candidates
+                        .iter()
+                        .position(|candidate| candidate.name == card.name)
+                        .or_else(|| (!candidates.is_empty()).then_some(0))?;
+                    Some(candidates.remove(idx))
+                });
+            (Some(card), other)
+        })
+        .collect_vec();
+
+    pairs.extend(
+        pkmn.iter()
+            .filter(|card| {
+                pkmn_by_number
+                    .get(card.number.as_str())
+                    .is_some_and(|left| left.iter().any(|other| std::ptr::eq(*other, *card)))
+            })
+            .map(|card| (None, Some(card))),
+    );
+    pairs
+}
+
+fn is_missing(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.is_empty(),
+        Value::Array(a) => a.is_empty(),
+        Value::Object(o) => o.is_empty(),
+        Value::Bool(_) | Value::Number(_) => false,
+    }
+}
+
+fn card_fields(card: Option<&Card>) -> Result<Map<String, Value>> {
+    Ok(match card.map(serde_json::to_value).transpose()? {
+        Some(Value::Object(map)) => map,
+        _ => Map::new(),
+    })
+}
+
+/// Builds a single card out of the cards both datasets have for the same (set, number)
+pub fn merge_card(
+    ptcgio: Option<&Card>,
+    pkmn: Option<&Card>,
+    policy: &Policy,
+) -> Result<(Card, Provenance)> {
+    let ptcgio_fields = card_fields(ptcgio)?;
+    let pkmn_fields = card_fields(pkmn)?;
+
+    let mut merged = Map::new();
+    let mut provenance = Provenance::new();
+    for key in ptcgio_fields.keys().chain(pkmn_fields.keys()).unique() {
+        let ptcgio_value = ptcgio_fields.get(key).filter(|value| !is_missing(value));
+        let pkmn_value = pkmn_fields.get(key).filter(|value| !is_missing(value));
+        let chosen = match policy.preferred(key) {
+            Source::Pkmn => pkmn_value
+                .map(|value| (Source::Pkmn, value))
+                .or(ptcgio_value.map(|value| (Source::Ptcgio, value))),
+            Source::Ptcgio | Source::Derived | Source::Override => ptcgio_value
+                .map(|value| (Source::Ptcgio, value))
+                .or(pkmn_value.map(|value| (Source::Pkmn, value))),
+        };
+
+        match chosen {
+            Some((source, value)) => {
+                provenance.insert(key.to_string(), source);
+                merged.insert(key.to_string(), value.clone());
+            }
+            None => {
+                let empty = ptcgio_fields
+                    .get(key)
+                    .or(pkmn_fields.get(key))
+                    .cloned()
+                    .unwrap_or(Value::Null);
+                merged.insert(key.to_string(), empty);
+            }
+        }
+    }
+
+    let card = serde_json::from_value(Value::Object(merged))
+        .context("Failed to rebuild card from the merged fields")?;
+    Ok((card, provenance))
+}
+
+/// Overwrites the paths of a merged card that reviewers made a decision for
+fn apply_decisions(
+    card: &mut Card,
+    provenance: &mut Provenance,
+    ptcgio: Option<&Card>,
+    pkmn: Option<&Card>,
+    decisions: &Decisions,
+    set_name: &str,
+) -> Result<()> {
+    let mut decided = decisions.for_card(set_name, &card.number).peekable();
+    if decided.peek().is_none() {
+        return Ok(());
+    }
+
+    let ptcgio_value = ptcgio.map(serde_json::to_value).transpose()?;
+    let pkmn_value = pkmn.map(serde_json::to_value).transpose()?;
+    let mut merged = serde_json::to_value(&*card)?;
+    for (path, decision) in decided {
+        let steps = parse_path(path)?;
+        let Some((source, value)) = Decisions::outcome(
+            decision,
+            ptcgio_value
+                .as_ref()
+                .and_then(|value| value_at_path(value, &steps)),
+            pkmn_value
+                .as_ref()
+                .and_then(|value| value_at_path(value, &steps)),
+        ) else {
+            continue;
+        };
+        set_at_path(&mut merged, &steps, value)
+            .with_context(|| format!("Failed to apply the decision for {}", path))?;
+        provenance.insert(path.to_string(), source);
+    }
+    *card = serde_json::from_value(merged)?;
+    Ok(())
+}
+
+/// Finds the ptcgio set a card id belongs to. Card ids are `<set id>-<number>` but numbers
+/// may themselves contain dashes, so the longest known prefix wins.
+pub(crate) fn set_id_of<'a>(card_id: &str, set_ids: &HashSet<&'a str>) -> Option<&'a str> {
+    card_id
+        .rmatch_indices('-')
+        .find_map(|(idx, _)| set_ids.get(&card_id[..idx]).copied())
+}
+
+/// Builds one card per (set, number) out of both datasets. Cards are placed in the ptcgio set
+/// their ptcgio id belongs to, so merged and renamed sets end up back in their original files.
+/// Cards only pkmncards has go into the set most of their siblings came from; sets without any
+/// ptcgio cards cannot be placed and are skipped.
+pub fn reconcile(
+    ptcgio_sets: &[Set],
+    ptcgio_data: &BTreeMap<String, Vec<Card>>,
+    pkmn_data: &BTreeMap<String, Vec<Card>>,
+    policy: &Policy,
+    decisions: &Decisions,
+) -> Result<Reconciled> {
+    let set_ids = ptcgio_sets
+        .iter()
+        .map(|set| set.id.as_str())
+        .collect::<HashSet<&str>>();
+    let empty = Vec::with_capacity(0);
+
+    let mut cards: HashMap<&str, Vec<Card>> = HashMap::new();
+    let mut provenance: BTreeMap<String, BTreeMap<String, Provenance>> = BTreeMap::new();
+
+    for set_name in ptcgio_data.keys().chain(pkmn_data.keys()).unique() {
+        let ptcgio_cards = ptcgio_data.get(set_name).unwrap_or(&empty);
+        let pkmn_cards = pkmn_data.get(set_name).unwrap_or(&empty);
+
+        let primary_set_id = ptcgio_cards
+            .iter()
+            .filter_map(|card| set_id_of(&card.id, &set_ids))
+            .counts()
+            .into_iter()
+            .max_by_key(|(_, count)| *count)
+            .map(|(id, _)| id);
+        let Some(primary_set_id) = primary_set_id else {
+            log::warn!(
+                "Skipping {} while reconciling as it has no ptcgio set to be placed in",
+                set_name
+            );
+            continue;
+        };
+
+        for (ptcgio_card, pkmn_card) in pair_cards(ptcgio_cards, pkmn_cards) {
+            let (mut card, mut card_provenance) = merge_card(ptcgio_card, pkmn_card, policy)
+                .with_context(|| format!("Failed to reconcile a card in {}", set_name))?;
+            apply_decisions(
+                &mut card,
+                &mut card_provenance,
+                ptcgio_card,
+                pkmn_card,
+                decisions,
+                set_name,
+            )?;
+
+            let set_id = match ptcgio_card {
+                Some(ptcgio_card) => set_id_of(&ptcgio_card.id, &set_ids).unwrap_or(primary_set_id),
+                None => {
+                    card.id = format!("{}-{}", primary_set_id, card.number);
+                    card_provenance.insert("id".to_string(), Source::Derived);
+                    primary_set_id
+                }
+            };
+
+            provenance
+                .entry(set_id.to_string())
+                .or_default()
+                .insert(card.id.clone(), card_provenance);
+            cards.entry(set_id).or_default().push(card);
+        }
+    }
+
+    let sets = ptcgio_sets
+        .iter()
+        .filter_map(|set| {
+            let set_cards = cards.remove(set.id.as_str())?;
+            let mut set = set.clone();
+            set.total = set_cards.len() as i32;
+            set.cards = set_cards;
+            Some(set)
+        })
+        .collect();
+
+    Ok(Reconciled { sets, provenance })
+}
+
+/// pokemon-tcg-data leaves absent fields out instead of writing them as null
+fn strip_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, strip_nulls(value)))
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(strip_nulls).collect()),
+        value => value,
+    }
+}
+
+async fn write_json(path: &Path, value: &impl Serialize) -> Result<()> {
+    let file = File::create(path)
+        .await
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    let output = serde_json::to_vec_pretty(&strip_nulls(serde_json::to_value(value)?))?;
+    writer.write_all(&output).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+impl Reconciled {
+    /// Writes the reconciled data in pokemon-tcg-data's layout of `sets/en.json` and
+    /// `cards/en/<set id>.json`, with the provenance of each set in `provenance/en/<set id>.json`
+    pub async fn write(&self, out_dir: &Path) -> Result<()> {
+        let sets_dir = out_dir.join("sets");
+        let cards_dir = out_dir.join("cards/en");
+        let provenance_dir = out_dir.join("provenance/en");
+        tokio::fs::create_dir_all(&sets_dir).await?;
+        tokio::fs::create_dir_all(&cards_dir).await?;
+        tokio::fs::create_dir_all(&provenance_dir).await?;
+
+        write_json(&sets_dir.join("en.json"), &self.sets).await?;
+        for set in &self.sets {
+            write_json(&cards_dir.join(&set.id).with_extension("json"), &set.cards).await?;
+            if let Some(provenance) = self.provenance.get(&set.id) {
+                write_json(
+                    &provenance_dir.join(&set.id).with_extension("json"),
+                    provenance,
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn names(pairs: &[(Option<&Card>, Option<&Card>)]) -> Vec<(Option<String>, Option<String>)> {
+        pairs
+            .iter()
+            .map(|(ptcgio, pkmn)| {
+                (
+                    ptcgio.map(|card| card.name.clone()),
+                    pkmn.map(|card| card.name.clone()),
+                )
+            })
+            .collect()
+    }
+
+    fn pair(ptcgio: &str, pkmn: &str) -> (Option<String>, Option<String>) {
+        (
+            (!ptcgio.is_empty()).then(|| ptcgio.to_string()),
+            (!pkmn.is_empty()).then(|| pkmn.to_string()),
+        )
+    }
+
+    #[test]
+    fn pair_by_number_then_name() {
+        let ptcgio = [
+            Card::stub("base1-1", "Alakazam"),
+            Card::stub("base1-2", "Blastoise"),
+            Card::stub("base1-3", "Chansey"),
+        ];
+        let pkmn = [
+            Card::stub("base1-2", "Blastoise"),
+            Card::stub("base1-1", "Alakazam"),
+            Card::stub("base1-4", "Charizard"),
+        ];
+        assert_eq!(
+            names(&pair_cards(&ptcgio, &pkmn)),
+            [
+                pair("Alakazam", "Alakazam"),
+                pair("Blastoise", "Blastoise"),
+                pair("Chansey", ""),
+                pair("", "Charizard"),
+            ]
+        );
+
+        // Numbers shared by several cards are paired by name first and in order after that
+        let ptcgio = [
+            Card::stub("pop-1", "Pikachu"),
+            Card::stub("pop-1", "Mew"),
+            Card::stub("pop-1", "Eevee"),
+        ];
+        let pkmn = [
+            Card::stub("pop-1", "Mew"),
+            Card::stub("pop-1", "Jolteon"),
+            Card::stub("pop-1", "Pikachu"),
+            Card::stub("pop-1", "Vaporeon"),
+        ];
+        assert_eq!(
+            names(&pair_cards(&ptcgio, &pkmn)),
+            [
+                pair("Pikachu", "Pikachu"),
+                pair("Mew", "Mew"),
+                pair("Eevee", "Jolteon"),
+                pair("", "Vaporeon"),
+            ]
+        );
+    }
+
+    fn alakazam() -> (Card, Card) {
+        let ptcgio = Card {
+            hp: Some("80".to_string()),
+            flavor_text: Some("Its brain can outperform a supercomputer.".to_string()),
+            rules: Some(Vec::new()),
+            legalities: BTreeMap::from([("unlimited".to_string(), "Legal".to_string())]),
+            images: BTreeMap::from([("small".to_string(), "1.png".to_string())]),
+            ..Card::stub("base1-1", "Alakazam")
+        };
+        let pkmn = Card {
+            flavor_text: Some("Its brain can outperform a super-computer.".to_string()),
+            rules: Some(vec!["Damage Swap can't be used while Asleep.".to_string()]),
+            artist: Some("Ken Sugimori".to_string()),
+            legalities: BTreeMap::from([("unlimited".to_string(), "Banned".to_string())]),
+            images: BTreeMap::from([("small".to_string(), "alakazam.png".to_string())]),
+            ..Card::stub("base1-1", "Alakazam")
+        };
+        (ptcgio, pkmn)
+    }
+
+    #[test]
+    fn merge_fields_by_policy() {
+        let (ptcgio, pkmn) = alakazam();
+        let (card, provenance) =
+            merge_card(Some(&ptcgio), Some(&pkmn), &Policy::default()).unwrap();
+
+        assert_eq!(card.flavor_text, pkmn.flavor_text);
+        assert_eq!(card.rules, pkmn.rules);
+        assert_eq!(card.legalities, ptcgio.legalities);
+        assert_eq!(card.images, ptcgio.images);
+        // Whatever the preferred side lacks comes from the other one
+        assert_eq!(card.hp, ptcgio.hp);
+        assert_eq!(card.artist, pkmn.artist);
+        assert_eq!(card.level, None);
+
+        assert_eq!(provenance["flavorText"], Source::Pkmn);
+        assert_eq!(provenance["rules"], Source::Pkmn);
+        assert_eq!(provenance["legalities"], Source::Ptcgio);
+        assert_eq!(provenance["images"], Source::Ptcgio);
+        assert_eq!(provenance["hp"], Source::Ptcgio);
+        assert_eq!(provenance["artist"], Source::Pkmn);
+        assert!(!provenance.contains_key("level"));
+    }
+
+    #[test]
+    fn merge_a_card_only_one_side_has() {
+        let (_, pkmn) = alakazam();
+        let (card, provenance) = merge_card(None, Some(&pkmn), &Policy::default()).unwrap();
+        assert_eq!(card, pkmn);
+        assert!(provenance.values().all(|source| *source == Source::Pkmn));
+    }
+
+    #[test]
+    fn reconcile_with_decisions() {
+        let (ptcgio, pkmn) = alakazam();
+        let sets: Vec<Set> = serde_json::from_value(json!([{
+            "id": "base1",
+            "name": "Base",
+            "series": "Base",
+            "printedTotal": 102,
+            "total": 102,
+            "legalities": {},
+            "releaseDate": "1999/01/09",
+            "updatedAt": "2020/08/14 09:35:00",
+            "images": {},
+        }]))
+        .unwrap();
+        let ptcgio_data = BTreeMap::from([("Base".to_string(), vec![ptcgio.clone()])]);
+        let pkmn_data = BTreeMap::from([(
+            "Base".to_string(),
+            vec![pkmn.clone(), Card::stub("base-2", "Blastoise")],
+        )]);
+        let decisions: Decisions = serde_json::from_value(json!({"Base": {"1": {
+            "flavorText": "accept-left",
+            "hp": {"override": "90"},
+        }}}))
+        .unwrap();
+
+        let reconciled = reconcile(
+            &sets,
+            &ptcgio_data,
+            &pkmn_data,
+            &Policy::default(),
+            &decisions,
+        )
+        .unwrap();
+        let [set] = reconciled.sets.as_slice() else {
+            panic!("Expected a single set, got {}", reconciled.sets.len());
+        };
+        assert_eq!(set.total, 2);
+        let [alakazam, blastoise] = set.cards.as_slice() else {
+            panic!("Expected two cards, got {}", set.cards.len());
+        };
+        assert_eq!(alakazam.flavor_text, ptcgio.flavor_text);
+        assert_eq!(alakazam.hp.as_deref(), Some("90"));
+        assert_eq!(blastoise.id, "base1-2");
+
+        let provenance = &reconciled.provenance["base1"];
+        assert_eq!(provenance["base1-1"]["flavorText"], Source::Ptcgio);
+        assert_eq!(provenance["base1-1"]["hp"], Source::Override);
+        assert_eq!(provenance["base1-1"]["rules"], Source::Pkmn);
+        assert_eq!(provenance["base1-2"]["id"], Source::Derived);
+        assert_eq!(provenance["base1-2"]["name"], Source::Pkmn);
+    }
+}
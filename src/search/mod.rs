@@ -0,0 +1,433 @@
+use crate::database::{Dataset, Sets};
+use crate::ptcgio_data::Card;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tantivy::collector::TopDocs;
+use tantivy::query::{BooleanQuery, ConstScoreQuery, Occur, Query, QueryParser, TermQuery};
+use tantivy::schema::{
+    Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value as _, STORED,
+};
+use tantivy::tokenizer::{LowerCaser, RawTokenizer, TextAnalyzer};
+use tantivy::{Index, IndexReader, ReloadPolicy, TantivyDocument, Term};
+
+/// Filter values are matched whole, ignoring case
+const KEYWORD_TOKENIZER: &str = "keyword";
+
+/// The file tantivy keeps in every index directory
+const INDEX_META: &str = "meta.json";
+
+/// What the index writer may buffer before flushing a segment
+const WRITER_MEMORY: usize = 50_000_000;
+
+/// A full-text index of the cards of datasets, over their name, attack and ability texts, rules
+/// and flavor text
+pub struct SearchIndex {
+    index: Index,
+    reader: IndexReader,
+    fields: Fields,
+}
+
+struct Fields {
+    dataset: Field,
+    set: Field,
+    supertype: Field,
+    types: Field,
+    name: Field,
+    attacks: Field,
+    abilities: Field,
+    rules: Field,
+    flavor_text: Field,
+    /// The hit the card is returned as, as JSON
+    hit: Field,
+}
+
+impl Fields {
+    fn schema() -> (Schema, Fields) {
+        let mut builder = Schema::builder();
+        let keyword = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(KEYWORD_TOKENIZER)
+                .set_index_option(IndexRecordOption::Basic),
+        );
+        let text = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer("en_stem")
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
+        let fields = Fields {
+            dataset: builder.add_text_field("dataset", keyword.clone()),
+            set: builder.add_text_field("set", keyword.clone()),
+            supertype: builder.add_text_field("supertype", keyword.clone()),
+            types: builder.add_text_field("types", keyword),
+            name: builder.add_text_field("name", text.clone()),
+            attacks: builder.add_text_field("attacks", text.clone()),
+            abilities: builder.add_text_field("abilities", text.clone()),
+            rules: builder.add_text_field("rules", text.clone()),
+            flavor_text: builder.add_text_field("flavor_text", text),
+            hit: builder.add_text_field("hit", STORED),
+        };
+        (builder.build(), fields)
+    }
+
+    fn document(&self, dataset: Dataset, set: &str, card: &Card) -> Result<TantivyDocument> {
+        let mut document = TantivyDocument::default();
+        document.add_text(self.dataset, dataset);
+        document.add_text(self.set, set);
+        document.add_text(self.supertype, &card.supertype);
+        for card_type in card.types.iter().flatten() {
+            document.add_text(self.types, card_type);
+        }
+        document.add_text(self.name, &card.name);
+        for attack in card.attacks.iter().flatten() {
+            if let Some(text) = attack.get("text").and_then(|text| text.as_str()) {
+                document.add_text(self.attacks, text);
+            }
+        }
+        for ability in card.abilities.iter().flatten() {
+            if let Some(text) = ability.get("text") {
+                document.add_text(self.abilities, text);
+            }
+        }
+        for rule in card.rules.iter().flatten() {
+            document.add_text(self.rules, rule);
+        }
+        if let Some(flavor_text) = &card.flavor_text {
+            document.add_text(self.flavor_text, flavor_text);
+        }
+        let hit = Hit {
+            score: 0.0,
+            dataset,
+            set: set.to_string(),
+            card: card.clone(),
+        };
+        document.add_text(self.hit, serde_json::to_string(&hit)?);
+        Ok(document)
+    }
+}
+
+/// Narrows a search down, filters left empty match every card
+#[derive(Debug, Default, Clone)]
+pub struct Filters {
+    /// The card is in any of them
+    pub datasets: Vec<Dataset>,
+    pub set: Option<String>,
+    pub supertype: Option<String>,
+    /// The card has any of them
+    pub types: Vec<String>,
+}
+
+/// A card the search found, with how well it matched
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Hit {
+    pub score: f32,
+    pub dataset: Dataset,
+    pub set: String,
+    pub card: Card,
+}
+
+impl SearchIndex {
+    /// Indexes the cards of the datasets into `dir`, replacing the index already there. Any other
+    /// directory is only built in when it's empty, so a mistyped `dir` can't delete anything else.
+    pub fn build(dir: &Path, datasets: &[(Dataset, Sets)]) -> Result<Self> {
+        if dir.join(INDEX_META).exists() {
+            std::fs::remove_dir_all(dir)
+                .with_context(|| format!("Failed to remove the old index {}", dir.display()))?;
+        } else if dir.exists() {
+            let mut entries = std::fs::read_dir(dir)
+                .with_context(|| format!("Failed to read {}", dir.display()))?;
+            if entries.next().is_some() {
+                bail!(
+                    "{} isn't a search index and isn't empty, refusing to replace it",
+                    dir.display()
+                );
+            }
+        }
+        std::fs::create_dir_all(dir)?;
+        let (schema, fields) = Fields::schema();
+        let index = Index::create_in_dir(dir, schema)?;
+        Self::index(index, fields, datasets)
+    }
+
+    /// Indexes the cards of the datasets without writing anything to disk
+    #[cfg(test)]
+    fn in_memory(datasets: &[(Dataset, Sets)]) -> Result<Self> {
+        let (schema, fields) = Fields::schema();
+        Self::index(Index::create_in_ram(schema), fields, datasets)
+    }
+
+    /// Opens an index `build` wrote
+    pub fn open(dir: &Path) -> Result<Self> {
+        let index = Index::open_in_dir(dir)
+            .with_context(|| format!("Failed to open the search index {}", dir.display()))?;
+        let (_, fields) = Fields::schema();
+        Self::with_reader(index, fields)
+    }
+
+    fn index(index: Index, fields: Fields, datasets: &[(Dataset, Sets)]) -> Result<Self> {
+        register_tokenizers(&index);
+        let mut writer = index.writer::<TantivyDocument>(WRITER_MEMORY)?;
+        for (dataset, sets) in datasets {
+            for (set, cards) in sets {
+                for card in cards {
+                    writer.add_document(fields.document(*dataset, set, card)?)?;
+                }
+            }
+        }
+        writer.commit()?;
+        Self::with_reader(index, fields)
+    }
+
+    fn with_reader(index: Index, fields: Fields) -> Result<Self> {
+        register_tokenizers(&index);
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        Ok(SearchIndex {
+            index,
+            reader,
+            fields,
+        })
+    }
+
+    /// The `limit` cards matching `text` best among those passing the filters, best first.
+    /// `text` is in tantivy's query syntax, so `"search your deck"` matches the phrase and
+    /// `rules:prize` a single field.
+    pub fn search(&self, text: &str, filters: &Filters, limit: usize) -> Result<Vec<Hit>> {
+        let fields = &self.fields;
+        let mut parser = QueryParser::for_index(
+            &self.index,
+            vec![
+                fields.name,
+                fields.attacks,
+                fields.abilities,
+                fields.rules,
+                fields.flavor_text,
+            ],
+        );
+        parser.set_field_boost(fields.name, 2.0);
+        let text_query = parser
+            .parse_query(text)
+            .with_context(|| format!("Failed to parse the search {:?}", text))?;
+
+        let mut clauses = vec![(Occur::Must, text_query)];
+        let datasets = filters.datasets.iter().map(ToString::to_string);
+        clauses.extend(any_of(fields.dataset, datasets));
+        clauses.extend(any_of(fields.set, filters.set.clone()));
+        clauses.extend(any_of(fields.supertype, filters.supertype.clone()));
+        clauses.extend(any_of(fields.types, filters.types.iter().cloned()));
+
+        let searcher = self.reader.searcher();
+        let top = searcher.search(&BooleanQuery::new(clauses), &TopDocs::with_limit(limit))?;
+        top.into_iter()
+            .map(|(score, address)| {
+                let document = searcher.doc::<TantivyDocument>(address)?;
+                let hit = document
+                    .get_first(fields.hit)
+                    .and_then(|hit| hit.as_str())
+                    .context("An indexed card has no hit stored")?;
+                Ok(Hit {
+                    score,
+                    ..serde_json::from_str(hit)?
+                })
+            })
+            .collect()
+    }
+}
+
+fn register_tokenizers(index: &Index) {
+    index.tokenizers().register(
+        KEYWORD_TOKENIZER,
+        TextAnalyzer::builder(RawTokenizer::default())
+            .filter(LowerCaser)
+            .build(),
+    );
+}
+
+/// A clause keeping the cards with any of `values` in `field` without changing their score, or
+/// none without values
+fn any_of(
+    field: Field,
+    values: impl IntoIterator<Item = String>,
+) -> Option<(Occur, Box<dyn Query>)> {
+    let terms = values
+        .into_iter()
+        .map(|value| {
+            let term = Term::from_field_text(field, &value.to_lowercase());
+            let query: Box<dyn Query> = Box::new(TermQuery::new(term, IndexRecordOption::Basic));
+            (Occur::Should, query)
+        })
+        .collect::<Vec<_>>();
+    if terms.is_empty() {
+        return None;
+    }
+    let filter = ConstScoreQuery::new(Box::new(BooleanQuery::new(terms)), 0.0);
+    Some((Occur::Must, Box::new(filter)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn card(id: &str, name: &str, supertype: &str, types: &[&str], attack: &str) -> Card {
+        let attack = BTreeMap::from([
+            ("name".to_string(), serde_json::json!("Attack")),
+            ("text".to_string(), serde_json::json!(attack)),
+        ]);
+        Card {
+            supertype: supertype.to_string(),
+            types: Some(types.iter().map(|t| t.to_string()).collect()),
+            attacks: Some(vec![attack]),
+            ..Card::stub(id, name)
+        }
+    }
+
+    fn datasets() -> Vec<(Dataset, Sets)> {
+        let trainer = Card {
+            supertype: "Trainer".to_string(),
+            rules: Some(vec![
+                "Discard 2 of the other cards from your hand in order to search your deck for \
+                 any card and put it into your hand. Shuffle your deck afterward."
+                    .to_string(),
+            ]),
+            ..Card::stub("base1-91", "Computer Search")
+        };
+        let base = vec![
+            card(
+                "base1-58",
+                "Pikachu",
+                "Pokémon",
+                &["Lightning"],
+                "Flip a coin. If tails, Pikachu does 10 damage to itself.",
+            ),
+            card(
+                "base1-4",
+                "Charizard",
+                "Pokémon",
+                &["Fire"],
+                "Discard 2 Energy cards attached to Charizard in order to use this attack.",
+            ),
+            trainer,
+        ];
+        let jungle = vec![card(
+            "base2-60",
+            "Pikachu",
+            "Pokémon",
+            &["Lightning"],
+            "Flip a coin. If heads, the Defending Pokémon is now Paralyzed.",
+        )];
+        vec![
+            (
+                Dataset::Ptcgio,
+                vec![
+                    ("Base".to_string(), base.clone()),
+                    ("Jungle".to_string(), jungle),
+                ],
+            ),
+            (Dataset::Pkmn, vec![("Base Set".to_string(), base)]),
+        ]
+    }
+
+    fn found(hits: &[Hit]) -> Vec<(Dataset, &str, &str)> {
+        hits.iter()
+            .map(|hit| (hit.dataset, hit.set.as_str(), hit.card.id.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn rank_cards_by_their_texts() {
+        let index = SearchIndex::in_memory(&datasets()).unwrap();
+        let filters = Filters {
+            datasets: vec![Dataset::Ptcgio],
+            ..Filters::default()
+        };
+        let hits = index.search("pikachu", &filters, 10).unwrap();
+        // Named Pikachu and mentioning it in the attack ranks above only being named Pikachu
+        assert_eq!(
+            found(&hits),
+            vec![
+                (Dataset::Ptcgio, "Base", "base1-58"),
+                (Dataset::Ptcgio, "Jungle", "base2-60"),
+            ]
+        );
+        assert!(hits[0].score > hits[1].score);
+
+        let hits = index.search("\"search your deck\"", &filters, 10).unwrap();
+        assert_eq!(found(&hits), vec![(Dataset::Ptcgio, "Base", "base1-91")]);
+        assert_eq!(hits[0].card.name, "Computer Search");
+
+        let hits = index.search("discarding energy", &filters, 1).unwrap();
+        assert_eq!(found(&hits), vec![(Dataset::Ptcgio, "Base", "base1-4")]);
+    }
+
+    #[test]
+    fn filter_hits() {
+        let index = SearchIndex::in_memory(&datasets()).unwrap();
+        let search = |filters: Filters| {
+            let hits = index.search("discard OR flip", &filters, 10).unwrap();
+            let mut ids = hits
+                .iter()
+                .map(|hit| format!("{}/{}", hit.dataset, hit.card.id))
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        let by_set = Filters {
+            set: Some("base set".to_string()),
+            ..Filters::default()
+        };
+        assert_eq!(
+            search(by_set),
+            vec!["pkmn/base1-4", "pkmn/base1-58", "pkmn/base1-91"]
+        );
+        let by_supertype = Filters {
+            datasets: vec![Dataset::Ptcgio],
+            supertype: Some("trainer".to_string()),
+            ..Filters::default()
+        };
+        assert_eq!(search(by_supertype), vec!["ptcgio/base1-91"]);
+        let by_types = Filters {
+            datasets: vec![Dataset::Ptcgio],
+            types: vec!["Fire".to_string(), "Water".to_string()],
+            ..Filters::default()
+        };
+        assert_eq!(search(by_types), vec!["ptcgio/base1-4"]);
+    }
+
+    #[test]
+    fn reopen_a_built_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index");
+        SearchIndex::build(&path, &datasets()).unwrap();
+        SearchIndex::build(&path, &datasets()[..1]).unwrap();
+        let index = SearchIndex::open(&path).unwrap();
+        let hits = index.search("paralyzed", &Filters::default(), 10).unwrap();
+        assert_eq!(found(&hits), vec![(Dataset::Ptcgio, "Jungle", "base2-60")]);
+        let hits = index.search("charizard", &Filters::default(), 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(SearchIndex::open(&dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn only_replace_indexes_or_empty_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "keep me").unwrap();
+        let error = SearchIndex::build(dir.path(), &datasets()).err().unwrap();
+        assert!(error.to_string().contains("isn't a search index"));
+        assert!(dir.path().join("notes.txt").exists());
+
+        let empty = dir.path().join("empty");
+        std::fs::create_dir(&empty).unwrap();
+        SearchIndex::build(&empty, &datasets()).unwrap();
+        assert!(empty.join(INDEX_META).exists());
+    }
+
+    #[test]
+    fn reject_malformed_searches() {
+        let index = SearchIndex::in_memory(&datasets()).unwrap();
+        let error = index.search("rules:", &Filters::default(), 10).unwrap_err();
+        assert!(error.to_string().contains("Failed to parse the search"));
+    }
+}
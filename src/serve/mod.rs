@@ -0,0 +1,326 @@
+use crate::database::{Dataset, Sets};
+use crate::ptcgio_data::Card;
+use crate::query::{Query, Subject};
+use anyhow::{Context, Result};
+use axum::extract::{Path as UrlPath, Query as UrlQuery, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use heck::ToSnekCase;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Everything the API serves, loaded once when it starts
+pub struct Api {
+    datasets: Vec<(Dataset, Sets)>,
+    /// The discrepancies the diff wrote, keyed by the directory of their set
+    diffs: BTreeMap<String, Value>,
+}
+
+impl Api {
+    pub fn new(datasets: Vec<(Dataset, Sets)>, diffs: BTreeMap<String, Value>) -> Self {
+        Api { datasets, diffs }
+    }
+
+    /// The sets of the requested dataset, or of all of them
+    fn sets(
+        &self,
+        dataset: Option<Dataset>,
+    ) -> impl Iterator<Item = (Dataset, &String, &Vec<Card>)> {
+        self.datasets
+            .iter()
+            .filter(move |(candidate, _)| dataset.is_none_or(|dataset| dataset == *candidate))
+            .flat_map(|(dataset, sets)| sets.iter().map(|(name, cards)| (*dataset, name, cards)))
+    }
+}
+
+/// Reads the `discrepancies.json` of every set in the diff's output directory
+pub async fn load_diffs(diffs_dir: &Path) -> Result<BTreeMap<String, Value>> {
+    let mut diffs = BTreeMap::new();
+    if !diffs_dir.exists() {
+        return Ok(diffs);
+    }
+    let mut entries = tokio::fs::read_dir(diffs_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path().join("discrepancies.json");
+        if path.exists() {
+            let contents = tokio::fs::read(&path).await?;
+            let discrepancies = serde_json::from_slice(&contents)
+                .with_context(|| format!("Failed to parse {}", path.display()))?;
+            diffs.insert(
+                entry.file_name().to_string_lossy().to_string(),
+                discrepancies,
+            );
+        }
+    }
+    Ok(diffs)
+}
+
+pub fn router(api: Arc<Api>) -> Router {
+    Router::new()
+        .route("/sets", get(sets))
+        .route("/sets/:name/cards", get(set_cards))
+        .route("/cards/:id", get(card))
+        .route("/diffs", get(diffs))
+        .route("/diffs/:set", get(set_diffs))
+        .route("/search", get(search))
+        .with_state(api)
+}
+
+/// Serves the API on `address` until the process is stopped
+pub async fn serve(api: Api, address: SocketAddr) -> Result<()> {
+    println!("Serving on http://{}", address);
+    axum::Server::try_bind(&address)?
+        .serve(router(Arc::new(api)).into_make_service())
+        .await?;
+    Ok(())
+}
+
+fn not_found(what: String) -> Response {
+    (StatusCode::NOT_FOUND, Json(json!({ "error": what }))).into_response()
+}
+
+#[derive(Deserialize)]
+struct DatasetFilter {
+    dataset: Option<Dataset>,
+}
+
+#[derive(Serialize)]
+struct SetSummary<'a> {
+    dataset: Dataset,
+    name: &'a str,
+    cards: usize,
+}
+
+async fn sets(State(api): State<Arc<Api>>, UrlQuery(filter): UrlQuery<DatasetFilter>) -> Response {
+    let sets = api
+        .sets(filter.dataset)
+        .map(|(dataset, name, cards)| SetSummary {
+            dataset,
+            name,
+            cards: cards.len(),
+        })
+        .collect::<Vec<_>>();
+    Json(sets).into_response()
+}
+
+/// The cards of the set in every dataset that has it, keyed by dataset
+async fn set_cards(
+    State(api): State<Arc<Api>>,
+    UrlPath(name): UrlPath<String>,
+    UrlQuery(filter): UrlQuery<DatasetFilter>,
+) -> Response {
+    let found = api
+        .sets(filter.dataset)
+        .filter(|(_, set, _)| **set == name)
+        .map(|(dataset, _, cards)| (dataset.to_string(), cards))
+        .collect::<BTreeMap<_, _>>();
+    if found.is_empty() {
+        return not_found(format!("No set named {}", name));
+    }
+    Json(found).into_response()
+}
+
+#[derive(Serialize)]
+struct Hit<'a> {
+    dataset: Dataset,
+    set: &'a str,
+    card: &'a Card,
+}
+
+/// The card with the id in every dataset that has it
+async fn card(
+    State(api): State<Arc<Api>>,
+    UrlPath(id): UrlPath<String>,
+    UrlQuery(filter): UrlQuery<DatasetFilter>,
+) -> Response {
+    let hits = api
+        .sets(filter.dataset)
+        .flat_map(|(dataset, set, cards)| {
+            cards
+                .iter()
+                .filter(|card| card.id == id)
+                .map(move |card| Hit { dataset, set, card })
+        })
+        .collect::<Vec<_>>();
+    if hits.is_empty() {
+        return not_found(format!("No card with the id {}", id));
+    }
+    Json(hits).into_response()
+}
+
+/// The sets the diff found discrepancies in, with how many
+async fn diffs(State(api): State<Arc<Api>>) -> Response {
+    let counts = api
+        .diffs
+        .iter()
+        .map(|(set, discrepancies)| {
+            let count = discrepancies.as_array().map_or(0, Vec::len);
+            (set.as_str(), count)
+        })
+        .collect::<BTreeMap<_, _>>();
+    Json(counts).into_response()
+}
+
+/// The discrepancies of a set, by its name or the directory the diff wrote it to
+async fn set_diffs(State(api): State<Arc<Api>>, UrlPath(set): UrlPath<String>) -> Response {
+    match api
+        .diffs
+        .get(&set)
+        .or_else(|| api.diffs.get(&set.to_snek_case()))
+    {
+        Some(discrepancies) => Json(discrepancies).into_response(),
+        None => not_found(format!("No diff for the set {}", set)),
+    }
+}
+
+#[derive(Deserialize)]
+struct Search {
+    /// A query in the language of the `query` command
+    q: String,
+    dataset: Option<Dataset>,
+    limit: Option<usize>,
+}
+
+async fn search(State(api): State<Arc<Api>>, UrlQuery(search): UrlQuery<Search>) -> Response {
+    let query = match search.q.parse::<Query>() {
+        Ok(query) => query,
+        Err(e) => {
+            let error = json!({ "error": e.to_string() });
+            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+        }
+    };
+    let mut hits = Vec::new();
+    for (dataset, set, cards) in api.sets(search.dataset) {
+        for card in cards {
+            if hits.len() == search.limit.unwrap_or(usize::MAX) {
+                break;
+            }
+            let matches = Subject::new(set, card).is_ok_and(|subject| query.matches(&subject));
+            if matches {
+                hits.push(Hit { dataset, set, card });
+            }
+        }
+    }
+    Json(hits).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(id: &str, name: &str, hp: &str) -> Card {
+        Card {
+            hp: Some(hp.to_string()),
+            ..Card::stub(id, name)
+        }
+    }
+
+    /// Serves a ptcgio and a pkmn dataset sharing the Base set, answering with the base URL
+    async fn start() -> String {
+        let datasets = vec![
+            (
+                Dataset::Ptcgio,
+                vec![(
+                    "Base".to_string(),
+                    vec![
+                        card("base1-58", "Pikachu", "40"),
+                        card("base1-14", "Raichu", "80"),
+                    ],
+                )],
+            ),
+            (
+                Dataset::Pkmn,
+                vec![("Base".to_string(), vec![card("base1-58", "Pikachu", "50")])],
+            ),
+        ];
+        let diffs = BTreeMap::from([(
+            "base_set".to_string(),
+            json!([{ "set": "Base Set", "number": "58", "path": "hp" }]),
+        )]);
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let api = Arc::new(Api::new(datasets, diffs));
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(router(api).into_make_service()),
+        );
+        format!("http://{}", address)
+    }
+
+    async fn get(url: String) -> (StatusCode, Value) {
+        let response = reqwest::get(url).await.unwrap();
+        (response.status(), response.json().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn serve_sets_and_cards() {
+        let base = start().await;
+        let (status, sets) = get(format!("{}/sets?dataset=pkmn", base)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            sets,
+            json!([{ "dataset": "pkmn", "name": "Base", "cards": 1 }])
+        );
+
+        let (_, cards) = get(format!("{}/sets/Base/cards", base)).await;
+        assert_eq!(cards["ptcgio"].as_array().unwrap().len(), 2);
+        assert_eq!(cards["pkmn"][0]["hp"], "50");
+
+        let (_, hits) = get(format!("{}/cards/base1-58", base)).await;
+        let datasets = hits
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|hit| hit["dataset"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(datasets, vec!["ptcgio", "pkmn"]);
+
+        let (status, error) = get(format!("{}/sets/Jungle/cards", base)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(error["error"], "No set named Jungle");
+    }
+
+    #[tokio::test]
+    async fn serve_diffs() {
+        let base = start().await;
+        let (_, counts) = get(format!("{}/diffs", base)).await;
+        assert_eq!(counts, json!({ "base_set": 1 }));
+        let (status, discrepancies) = get(format!("{}/diffs/Base%20Set", base)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(discrepancies[0]["path"], "hp");
+        let (status, _) = get(format!("{}/diffs/Jungle", base)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn search_with_queries() {
+        let base = start().await;
+        let (_, hits) = get(format!("{}/search?q=hp%20%3E%2045", base)).await;
+        let names = hits
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|hit| {
+                (
+                    hit["dataset"].as_str().unwrap(),
+                    hit["card"]["name"].as_str().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![("ptcgio", "Raichu"), ("pkmn", "Pikachu")]);
+
+        let (_, hits) = get(format!("{}/search?q=hp%20%3E%2045&limit=1", base)).await;
+        assert_eq!(hits.as_array().unwrap().len(), 1);
+
+        let (status, error) = get(format!("{}/search?q=hp%20%3E", base)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["error"], "Expected a value at 4");
+    }
+}